@@ -1,168 +1,5 @@
-use std::collections::{BTreeMap, VecDeque};
-
-#[derive(PartialEq, Debug)]
-enum Opcode {
-    Add,
-    Mul,
-    Input,
-    Output,
-    JumpTrue,
-    JumpFalse,
-    LessThan,
-    Equal,
-    RelativeBase,
-    Halt,
-}
-impl From<isize> for Opcode {
-    fn from(value: isize) -> Self {
-        match value {
-            1 => Opcode::Add,
-            2 => Opcode::Mul,
-            3 => Opcode::Input,
-            4 => Opcode::Output,
-            5 => Opcode::JumpTrue,
-            6 => Opcode::JumpFalse,
-            7 => Opcode::LessThan,
-            8 => Opcode::Equal,
-            9 => Opcode::RelativeBase,
-            99 => Opcode::Halt,
-            val => unreachable!(val),
-        }
-    }
-}
-
-#[derive(Debug)]
-enum ParamMode {
-    Immediate,
-    Position,
-    Relative,
-}
-
-#[derive(Debug)]
-struct Program {
-    input: VecDeque<isize>,
-    instructions: Vec<isize>,
-    last_diag_code: isize,
-    cursor: isize,
-    relative_base: isize,
-}
-
-impl Program {
-    fn new(instructions: &[isize], sequence: &[isize]) -> Self {
-        let mut memory = instructions.to_vec();
-        memory.resize(instructions.len() * 10, 0);
-        Self {
-            input: VecDeque::from(sequence.to_vec()),
-            instructions: memory,
-            last_diag_code: 0,
-            cursor: 0,
-            relative_base: 0,
-        }
-    }
-
-    fn step(&mut self) -> Opcode {
-        let opcode = self.opcode();
-        match opcode {
-            Opcode::Add => {
-                self.insert(3, self.param(1) + self.param(2));
-                self.cursor += 4;
-            }
-            Opcode::Mul => {
-                self.insert(3, self.param(1) * self.param(2));
-                self.cursor += 4;
-            }
-            Opcode::Input => {
-                if let Some(input) = self.input.pop_front() {
-                    self.insert(1, input);
-                    self.cursor += 2;
-                }
-            }
-            Opcode::Output => {
-                self.last_diag_code = self.param(1);
-                self.cursor += 2;
-            }
-            Opcode::JumpTrue => {
-                if self.param(1) > 0 {
-                    self.cursor = self.param(2);
-                } else {
-                    self.cursor += 3;
-                }
-            }
-            Opcode::JumpFalse => {
-                if self.param(1) == 0 {
-                    self.cursor = self.param(2);
-                } else {
-                    self.cursor += 3;
-                }
-            }
-            Opcode::LessThan => {
-                self.insert(3, (self.param(1) < self.param(2)).into());
-                self.cursor += 4;
-            }
-            Opcode::RelativeBase => {
-                self.relative_base += self.param(1);
-                self.cursor += 2;
-            }
-            Opcode::Equal => {
-                self.insert(3, (self.param(1) == self.param(2)).into());
-                self.cursor += 4;
-            }
-            _ => {}
-        };
-        opcode
-    }
-
-    fn run(&mut self) -> bool {
-        loop {
-            if self.step() == Opcode::Halt {
-                return true;
-            }
-        }
-    }
-
-    fn opcode(&self) -> Opcode {
-        (self.instructions[self.cursor as usize])
-            .rem_euclid(100)
-            .into()
-    }
-
-    fn param(&self, offset: isize) -> isize {
-        let value = self.instructions[(self.cursor + offset) as usize];
-        match self.param_mode(offset) {
-            ParamMode::Immediate => value,
-            ParamMode::Position => self.instructions[value as usize],
-            ParamMode::Relative => self.instructions[(self.relative_base + value) as usize],
-        }
-    }
-
-    fn insert(&mut self, offset: isize, value: isize) {
-        let position = match self.param_mode(offset) {
-            ParamMode::Position => self.instructions[(self.cursor + offset) as usize],
-            ParamMode::Relative => {
-                self.relative_base + self.instructions[(self.cursor + offset) as usize]
-            }
-            _ => unreachable!(),
-        };
-        self.instructions[position as usize] = value;
-    }
-
-    fn param_mode(&self, offset: isize) -> ParamMode {
-        let opcode = self.instructions[self.cursor as usize];
-        match (opcode / 10isize.pow(offset as u32 + 1)).rem_euclid(10) {
-            1 => ParamMode::Immediate,
-            2 => ParamMode::Relative,
-            _ => ParamMode::Position,
-        }
-    }
-
-    fn add_input(&mut self, input: isize) {
-        self.input.push_back(input);
-    }
-
-    fn diag_code(&self) -> isize {
-        self.last_diag_code
-    }
-}
+use intcode::{Program, Status};
+use std::collections::BTreeMap;
 
 #[derive(PartialEq)]
 enum Panel {
@@ -204,11 +41,7 @@ impl From<isize> for Direction {
 }
 
 fn part1(input: &str, starting_color: isize) -> BTreeMap<(i32, i32), Panel> {
-    let input: Vec<_> = input
-        .trim()
-        .split(',')
-        .flat_map(|v| v.parse::<isize>().ok())
-        .collect();
+    let input = intcode::parse(input);
 
     let mut robot = BTreeMap::new();
     let mut program = Program::new(&input, &[starting_color]);
@@ -218,14 +51,14 @@ fn part1(input: &str, starting_color: isize) -> BTreeMap<(i32, i32), Panel> {
     let mut is_color_output = true;
     let mut facing_direction = Direction::Up;
     loop {
-        match program.step() {
-            Opcode::Output => {
+        match program.step().unwrap() {
+            Status::Output(value) => {
                 if is_color_output {
-                    let color: Panel = program.diag_code().into();
+                    let color: Panel = value.into();
                     *robot.entry(position).or_insert(Panel::Black) = color;
                     is_color_output = false;
                 } else {
-                    facing_direction = match program.diag_code().into() {
+                    facing_direction = match value.into() {
                         Direction::Left => match facing_direction {
                             Direction::Left => Direction::Down,
                             Direction::Right => Direction::Up,
@@ -250,7 +83,7 @@ fn part1(input: &str, starting_color: isize) -> BTreeMap<(i32, i32), Panel> {
                     is_color_output = true;
                 }
             }
-            Opcode::Halt => break,
+            Status::Halted => break,
             _ => continue,
         }
     }
@@ -1,166 +1,25 @@
+use intcode::network::Network;
+use intcode::{Engine, Program};
 use itertools::Itertools;
-use std::collections::VecDeque;
 
-#[derive(PartialEq)]
-enum Opcode {
-    Add,
-    Mul,
-    Input,
-    Output,
-    JumpTrue,
-    JumpFalse,
-    LessThan,
-    Equal,
-    Halt,
-}
-impl From<usize> for Opcode {
-    fn from(value: usize) -> Self {
-        match value {
-            1 => Opcode::Add,
-            2 => Opcode::Mul,
-            3 => Opcode::Input,
-            4 => Opcode::Output,
-            5 => Opcode::JumpTrue,
-            6 => Opcode::JumpFalse,
-            7 => Opcode::LessThan,
-            8 => Opcode::Equal,
-            99 => Opcode::Halt,
-            _ => unreachable!(),
-        }
-    }
+fn load(input: &str) -> Program {
+    let mut program = Program::new(&intcode::parse(input), &[]);
+    program.set_engine(Engine::Threaded);
+    program
 }
 
-enum ParamMode {
-    Immediate,
-    Position,
-}
-
-#[derive(Debug)]
-struct Program {
-    input: VecDeque<usize>,
-    instructions: Vec<isize>,
-    last_diag_code: Option<usize>,
-    cursor: usize,
-}
-
-impl Program {
-    fn new(instructions: &[isize], sequence: &[usize]) -> Self {
-        Self {
-            input: VecDeque::from(sequence.to_vec()),
-            instructions: instructions.to_vec(),
-            last_diag_code: None,
-            cursor: 0,
-        }
-    }
-
-    fn step(&mut self) -> Opcode {
-        let opcode = self.opcode();
-        match opcode {
-            Opcode::Add => {
-                self.insert(3, self.param(1) + self.param(2));
-                self.cursor += 4;
-            }
-            Opcode::Mul => {
-                self.insert(3, self.param(1) * self.param(2));
-                self.cursor += 4;
-            }
-            Opcode::Input => {
-                if let Some(input) = self.input.pop_front() {
-                    self.insert(1, input as isize);
-                    self.cursor += 2;
-                }
-            }
-            Opcode::Output => {
-                let addr = self.instructions[self.cursor + 1];
-                self.last_diag_code = Some(self.instructions[addr as usize] as usize);
-                self.cursor += 2;
-            }
-            Opcode::JumpTrue => {
-                if self.param(1) > 0 {
-                    self.cursor = self.param(2) as usize;
-                } else {
-                    self.cursor += 3;
-                }
-            }
-            Opcode::JumpFalse => {
-                if self.param(1) == 0 {
-                    self.cursor = self.param(2) as usize;
-                } else {
-                    self.cursor += 3;
-                }
-            }
-            Opcode::LessThan => {
-                self.insert(3, (self.param(1) < self.param(2)).into());
-                self.cursor += 4;
-            }
-            Opcode::Equal => {
-                self.insert(3, (self.param(1) == self.param(2)).into());
-                self.cursor += 4;
-            }
-            _ => {}
-        };
-        opcode
-    }
-
-    fn run(&mut self) -> bool {
-        loop {
-            if self.step() == Opcode::Halt {
-                return true;
-            }
-        }
-    }
-
-    fn opcode(&self) -> Opcode {
-        (self.instructions[self.cursor] as usize)
-            .rem_euclid(100)
-            .into()
-    }
-
-    fn param(&self, offset: usize) -> isize {
-        let value = self.instructions[self.cursor + offset];
-        match self.param_mode(offset) {
-            ParamMode::Immediate => value,
-            ParamMode::Position => self.instructions[value as usize],
-        }
-    }
-
-    fn insert(&mut self, offset: usize, value: isize) {
-        let addr = self.instructions[self.cursor + offset];
-        self.instructions[addr as usize] = value;
-    }
-
-    fn add_input(&mut self, input: usize) {
-        self.input.push_back(input);
-    }
-
-    fn param_mode(&self, offset: usize) -> ParamMode {
-        let opcode = self.instructions[self.cursor] as usize;
-        if (opcode / 10usize.pow(offset as u32 + 1)).rem_euclid(10) == 1 {
-            ParamMode::Immediate
-        } else {
-            ParamMode::Position
-        }
-    }
-
-    fn diag_code(&self) -> Option<usize> {
-        self.last_diag_code
-    }
-}
-
-fn part1(input: &str) -> usize {
-    let input: Vec<_> = input
-        .trim()
-        .split(',')
-        .flat_map(|v| v.parse::<isize>().ok())
-        .collect();
+fn part1(input: &str) -> isize {
+    let amplifier = load(input);
     (0..=4)
         .permutations(5)
         .map(|sequence| {
             let mut last = 0;
             sequence.iter().for_each(|v| {
-                let mut program = Program::new(&input, &[*v, last]);
-                program.run();
-                last = program.diag_code().unwrap_or(0);
+                let mut program = amplifier.clone();
+                program.add_input(*v);
+                program.add_input(last);
+                program.run().unwrap();
+                last = program.diag_code();
             });
             last
         })
@@ -168,36 +27,23 @@ fn part1(input: &str) -> usize {
         .unwrap_or(0)
 }
 
-fn part2(input: &str) -> usize {
-    let input: Vec<_> = input
-        .trim()
-        .split(',')
-        .flat_map(|v| v.parse::<isize>().ok())
-        .collect();
+fn part2(input: &str) -> isize {
+    let amplifier = load(input);
     let mut bests = Vec::new();
     for sequence in (5..=9).permutations(5) {
-        let mut programs = Vec::new();
-        for value in sequence.iter() {
-            programs.push(Program::new(&input, &[*value]));
-        }
-        let mut last_value = 0;
-        'feedback: loop {
-            for (idx, program) in programs.iter_mut().enumerate() {
-                program.add_input(last_value);
-                loop {
-                    match program.step() {
-                        Opcode::Output => {
-                            last_value = program.diag_code().unwrap_or(0);
-                            break;
-                        }
-                        Opcode::Halt if idx == 4 => break 'feedback,
-                        Opcode::Halt => break,
-                        _ => continue,
-                    }
-                }
+        let mut network = Network::new();
+        for (idx, value) in sequence.iter().enumerate() {
+            let mut program = amplifier.clone();
+            program.add_input(*value);
+            if idx == 0 {
+                program.add_input(0);
             }
+            network.add(program);
+        }
+        for idx in 0..sequence.len() {
+            network.connect(idx, (idx + 1) % sequence.len()).unwrap();
         }
-        bests.push(last_value);
+        bests.push(network.run(sequence.len() - 1).unwrap());
     }
     *bests.iter().max().unwrap_or(&0)
 }
@@ -0,0 +1,106 @@
+//! Compares the reference and threaded engines on the puzzle workloads that
+//! run the most instructions. Run with `cargo bench -p intcode`.
+
+use intcode::{Engine, Program};
+use std::time::{Duration, Instant};
+
+const DAY2: &str = include_str!("../../day2/src/input.txt");
+const DAY7: &str = include_str!("../../day7/src/input.txt");
+const DAY9: &str = include_str!("../../day9/src/input.txt");
+
+type Workload = fn(&Program) -> isize;
+
+/// Every noun and verb, each run on a copy of the loaded program.
+fn day2(template: &Program) -> isize {
+    let mut checksum = 0;
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut program = template.clone();
+            program.write(1, noun).unwrap();
+            program.write(2, verb).unwrap();
+            program.run().unwrap();
+            checksum ^= program.read(0).unwrap();
+        }
+    }
+    checksum
+}
+
+fn permutations(values: &mut Vec<isize>, k: usize, out: &mut Vec<Vec<isize>>) {
+    if k == values.len() {
+        out.push(values.clone());
+    }
+    for i in k..values.len() {
+        values.swap(k, i);
+        permutations(values, k + 1, out);
+        values.swap(k, i);
+    }
+}
+
+/// The amplifier chain for every phase permutation.
+fn day7(template: &Program) -> isize {
+    let mut sequences = Vec::new();
+    permutations(&mut (0..=4).collect(), 0, &mut sequences);
+    let mut best = 0;
+    for sequence in sequences {
+        let mut signal = 0;
+        for phase in sequence {
+            let mut program = template.clone();
+            program.add_input(phase);
+            program.add_input(signal);
+            program.run().unwrap();
+            signal = program.diag_code();
+        }
+        best = best.max(signal);
+    }
+    best
+}
+
+/// The BOOST program in sensor mode, a long running loop.
+fn day9(template: &Program) -> isize {
+    let mut program = template.clone();
+    program.add_input(2);
+    program.run().unwrap();
+    program.diag_code()
+}
+
+/// The fastest of several runs, after a warm-up.
+fn time(workload: Workload, template: &Program, rounds: u32) -> (isize, Duration) {
+    let answer = workload(template);
+    let best = (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..rounds {
+                assert_eq!(answer, workload(template));
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    (answer, best)
+}
+
+fn main() {
+    let workloads: [(&str, &str, Workload, u32); 3] = [
+        ("day2 sweep", DAY2, day2, 5),
+        ("day7 phases", DAY7, day7, 100),
+        ("day9 boost", DAY9, day9, 3),
+    ];
+    println!(
+        "{:<12} {:>12} {:>12} {:>8}",
+        "workload", "reference", "threaded", "speedup"
+    );
+    for (name, source, workload, rounds) in workloads.iter() {
+        let mut template = Program::new(&intcode::parse(source), &[]);
+        let (expected, reference) = time(*workload, &template, *rounds);
+        template.set_engine(Engine::Threaded);
+        let (answer, threaded) = time(*workload, &template, *rounds);
+        assert_eq!(expected, answer, "{} differs between engines", name);
+        println!(
+            "{:<12} {:>10.2}ms {:>10.2}ms {:>7.2}x",
+            name,
+            reference.as_secs_f64() * 1000.0,
+            threaded.as_secs_f64() * 1000.0,
+            reference.as_secs_f64() / threaded.as_secs_f64()
+        );
+    }
+}
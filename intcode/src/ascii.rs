@@ -0,0 +1,86 @@
+use crate::io::{AsciiOutput, NoInput};
+use crate::{Program, Status, VmError};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug)]
+pub enum DriverError {
+    Io(io::Error),
+    Vm(VmError),
+}
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriverError::Io(err) => write!(f, "{}", err),
+            DriverError::Vm(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for DriverError {}
+
+impl From<io::Error> for DriverError {
+    fn from(err: io::Error) -> Self {
+        DriverError::Io(err)
+    }
+}
+
+impl From<VmError> for DriverError {
+    fn from(err: VmError) -> Self {
+        DriverError::Vm(err)
+    }
+}
+
+/// Plays a program that speaks ASCII: its text is written to `output` as it
+/// is produced, and whenever it waits for input a line is read from `input`.
+/// Returns the non-ASCII values it output once it halts or `input` ends.
+pub fn interact<R, W>(
+    program: &mut Program,
+    mut input: R,
+    mut output: W,
+) -> Result<Vec<isize>, DriverError>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut sink = AsciiOutput::new();
+    loop {
+        let status = program.run_with(&mut NoInput, &mut sink)?;
+        output.write_all(sink.take_text().as_bytes())?;
+        output.flush()?;
+        if status != Status::NeedsInput {
+            break;
+        }
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            break;
+        }
+        program.add_ascii(line.trim_end_matches(['\n', '\r']));
+    }
+    Ok(sink.take_values())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interact() {
+        let echo = crate::asm::assemble(
+            "OUT #62\nloop: IN [c]\nOUT [c]\nEQ [c], #10, [t]\nJF [t], #loop\nOUT #1000\nHLT\nc: .data 0\nt: .data 0",
+        )
+        .unwrap();
+        let mut program = Program::new(&echo, &[]);
+        let mut screen = Vec::new();
+        let values = interact(&mut program, &b"hello\r\n"[..], &mut screen).unwrap();
+        assert_eq!(vec![1000], values);
+        assert_eq!(">hello\n", String::from_utf8(screen).unwrap());
+
+        let mut program = Program::new(&echo, &[]);
+        let values = interact(&mut program, &b""[..], io::sink()).unwrap();
+        assert!(values.is_empty());
+        assert_eq!(Ok(Status::NeedsInput), program.step());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{Instruction, Opcode, Param, ParamMode};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// A problem in assembler source, with the 1-based line it was found on.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+enum Value<'a> {
+    Number(isize),
+    Label(&'a str, isize),
+}
+
+enum Item<'a> {
+    Code(Opcode, Vec<(ParamMode, Value<'a>)>),
+    Data(Vec<Value<'a>>),
+}
+
+/// Assembles source into the words `Program::new` consumes.
+///
+/// Each line holds an optional `label:`, then either an instruction such as
+/// `ADD [9], #-3, rb+7` or a `.data 1, 2, label` directive. Operands are
+/// `#value` (immediate), `[value]` (position) or `rb+value` (relative), where
+/// a value is a number, a label, or a label with a `+n`/`-n` offset.
+/// Everything after `;` is a comment.
+pub fn assemble(source: &str) -> Result<Vec<isize>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut addr = 0;
+    for (idx, line) in source.lines().enumerate() {
+        let fail = |message: String| AsmError {
+            line: idx + 1,
+            message,
+        };
+        let mut line = line.split(';').next().unwrap_or("").trim();
+        if let Some(colon) = line.find(':') {
+            let label = line[..colon].trim();
+            if !is_label(label) {
+                return Err(fail(format!("invalid label `{}`", label)));
+            }
+            if labels.insert(label, addr).is_some() {
+                return Err(fail(format!("duplicate label `{}`", label)));
+            }
+            line = line[colon + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (mnemonic, operands) = match line.find(char::is_whitespace) {
+            Some(space) => (&line[..space], line[space..].trim()),
+            None => (line, ""),
+        };
+        let operands: Vec<_> = if operands.is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(str::trim).collect()
+        };
+        let item = if mnemonic == ".data" || mnemonic.eq_ignore_ascii_case("DATA") {
+            if operands.is_empty() {
+                return Err(fail("`.data` needs at least one value".to_string()));
+            }
+            let values = operands
+                .iter()
+                .map(|operand| value(operand).map_err(&fail))
+                .collect::<Result<Vec<_>, _>>()?;
+            Item::Data(values)
+        } else {
+            let opcode = Opcode::from_mnemonic(mnemonic)
+                .ok_or_else(|| fail(format!("unknown mnemonic `{}`", mnemonic)))?;
+            if operands.len() != opcode.arity() {
+                return Err(fail(format!(
+                    "{} takes {} operands, found {}",
+                    opcode.mnemonic(),
+                    opcode.arity(),
+                    operands.len()
+                )));
+            }
+            let mut params = Vec::new();
+            for (offset, operand) in operands.iter().enumerate() {
+                let param = self::operand(operand).map_err(&fail)?;
+                if param.0 == ParamMode::Immediate && opcode.write_param() == Some(offset + 1) {
+                    return Err(fail(format!(
+                        "operand {} of {} is written and cannot be immediate",
+                        offset + 1,
+                        opcode.mnemonic()
+                    )));
+                }
+                params.push(param);
+            }
+            Item::Code(opcode, params)
+        };
+        addr += match &item {
+            Item::Code(opcode, _) => 1 + opcode.arity() as isize,
+            Item::Data(values) => values.len() as isize,
+        };
+        items.push((idx + 1, item));
+    }
+
+    let mut words = Vec::new();
+    for (line, item) in items {
+        let resolve = |value: &Value| resolve(&labels, line, value);
+        match item {
+            Item::Code(opcode, params) => {
+                let params = params
+                    .iter()
+                    .map(|(mode, value)| {
+                        let value = resolve(value)?;
+                        Ok(Param { mode: *mode, value })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                words.extend(Instruction { opcode, params }.encode());
+            }
+            Item::Data(values) => {
+                for value in values.iter() {
+                    words.push(resolve(value)?);
+                }
+            }
+        }
+    }
+    Ok(words)
+}
+
+fn resolve(labels: &HashMap<&str, isize>, line: usize, value: &Value) -> Result<isize, AsmError> {
+    match *value {
+        Value::Number(number) => Ok(number),
+        Value::Label(label, offset) => match labels.get(label) {
+            Some(addr) => addr.checked_add(offset).ok_or_else(|| AsmError {
+                line,
+                message: format!("`{}{:+}` is out of range", label, offset),
+            }),
+            None => Err(AsmError {
+                line,
+                message: format!("undefined label `{}`", label),
+            }),
+        },
+    }
+}
+
+fn operand(operand: &str) -> Result<(ParamMode, Value<'_>), String> {
+    if let Some(rest) = operand.strip_prefix('#') {
+        Ok((ParamMode::Immediate, value(rest)?))
+    } else if operand.starts_with('[') && operand.ends_with(']') {
+        Ok((ParamMode::Position, value(&operand[1..operand.len() - 1])?))
+    } else if let Some(rest) = operand.strip_prefix("rb") {
+        let rest = rest.trim();
+        let value = match rest.chars().next() {
+            None => Value::Number(0),
+            Some('+') => value(&rest[1..])?,
+            Some('-') => match value(&rest[1..])? {
+                Value::Number(number) => match number.checked_neg() {
+                    Some(number) => Value::Number(number),
+                    None => return Err(format!("invalid relative operand `{}`", operand)),
+                },
+                Value::Label(..) => return Err(format!("cannot negate label in `{}`", operand)),
+            },
+            _ => return Err(format!("invalid relative operand `{}`", operand)),
+        };
+        Ok((ParamMode::Relative, value))
+    } else {
+        Err(format!(
+            "invalid operand `{}`, expected `#value`, `[value]` or `rb+value`",
+            operand
+        ))
+    }
+}
+
+fn value(value: &str) -> Result<Value<'_>, String> {
+    let value = value.trim();
+    if let Ok(number) = value.parse::<isize>() {
+        return Ok(Value::Number(number));
+    }
+    let (label, offset) = match value.rfind(['+', '-']) {
+        Some(sign) if sign > 0 => {
+            let offset = value[sign..]
+                .replace(' ', "")
+                .parse::<isize>()
+                .map_err(|_| format!("invalid offset in `{}`", value))?;
+            (value[..sign].trim(), offset)
+        }
+        _ => (value, 0),
+    };
+    if is_label(label) {
+        Ok(Value::Label(label, offset))
+    } else {
+        Err(format!("invalid value `{}`", value))
+    }
+}
+
+fn is_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disasm::{disassemble, Line};
+    use crate::{Program, Status};
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ; count down from the input, printing each value
+                    IN [counter]
+            loop:   OUT [counter]
+                    ADD [counter], #-1, [counter]
+                    JT [counter], #loop
+                    HLT
+            counter: .data 0
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0],
+            program
+        );
+        let mut vm = Program::new(&program, &[3]);
+        let mut outputs = Vec::new();
+        loop {
+            match vm.step().unwrap() {
+                Status::Output(value) => outputs.push(value),
+                Status::Halted => break,
+                _ => continue,
+            }
+        }
+        assert_eq!(vec![3, 2, 1], outputs);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = "ARB #10\nIN rb-2\nMUL rb-2, #3, [12]\nOUT [12]\nHLT\nDATA 7\nDATA -1";
+        let program = assemble(source).unwrap();
+        let lines: Vec<_> = disassemble(&program)
+            .iter()
+            .map(|line| match line {
+                Line::Code { instruction, .. } => instruction.to_string(),
+                Line::Data { value, .. } => format!("DATA {}", value),
+            })
+            .collect();
+        assert_eq!(source, lines.join("\n"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Err(AsmError {
+                line: 2,
+                message: "undefined label `nowhere`".to_string()
+            }),
+            assemble("HLT\nJT #1, #nowhere")
+        );
+        assert_eq!(
+            Err(AsmError {
+                line: 1,
+                message: "operand 1 of IN is written and cannot be immediate".to_string()
+            }),
+            assemble("IN #4")
+        );
+        assert_eq!(
+            Err(AsmError {
+                line: 1,
+                message: "`end+9223372036854775807` is out of range".to_string()
+            }),
+            assemble("JT #1, #end+9223372036854775807\nend: HLT")
+        );
+        assert!(assemble("OUT rb--9223372036854775808").is_err());
+        assert!(assemble("ADD [1], [2]").is_err());
+        assert!(assemble("FOO").is_err());
+    }
+}
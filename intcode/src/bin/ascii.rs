@@ -0,0 +1,29 @@
+use intcode::Program;
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: ascii <program>");
+            process::exit(2);
+        }
+    };
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+
+    let mut program = Program::new(&intcode::parse(&source), &[]);
+    let stdin = io::stdin();
+    match intcode::ascii::interact(&mut program, stdin.lock(), io::stdout()) {
+        Ok(values) => values.iter().for_each(|value| println!("{}", value)),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
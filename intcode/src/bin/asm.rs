@@ -0,0 +1,27 @@
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: asm <source>");
+            process::exit(2);
+        }
+    };
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    match intcode::asm::assemble(&source) {
+        Ok(words) => {
+            let words: Vec<_> = words.iter().map(|w| w.to_string()).collect();
+            println!("{}", words.join(","));
+        }
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use intcode::debugger::{Debugger, Stop};
+use intcode::Program;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: debugger <program> [--input 1,2,3] [--history 100000]");
+    process::exit(2);
+}
+
+fn prompt(text: &str) -> Option<String> {
+    print!("{}", text);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut path = None;
+    let mut input = Vec::new();
+    let mut history = 100_000;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => match args.next() {
+                Some(values) => input.extend(intcode::parse(&values)),
+                None => usage(),
+            },
+            "--history" => match args.next().and_then(|window| window.parse().ok()) {
+                Some(window) => history = window,
+                None => usage(),
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+
+    let mut program = Program::new(&intcode::parse(&source), &input);
+    program.set_history(Some(history));
+    let mut debugger = Debugger::new(program);
+    println!("{}", debugger.registers());
+    while let Some(line) = prompt("(icdb) ") {
+        let line = line.trim();
+        if line == "quit" || line == "q" {
+            break;
+        }
+        match debugger.command(line) {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => println!("{}", text),
+            Err(err) => eprintln!("{}", err),
+        }
+        // The command just blocked with nothing queued, so ask for the
+        // values the program is waiting on.
+        if debugger.take_last_stop() == Some(Stop::NeedsInput)
+            && debugger.program().pending_input() == 0
+        {
+            match prompt("input> ") {
+                Some(values) => intcode::parse(&values)
+                    .into_iter()
+                    .for_each(|value| debugger.program_mut().add_input(value)),
+                None => break,
+            }
+        }
+    }
+}
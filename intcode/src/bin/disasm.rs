@@ -0,0 +1,63 @@
+use intcode::cfg::ControlFlowGraph;
+use intcode::coverage::Coverage;
+use std::env;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: disasm <program> [--coverage <path> | --dot | --decompile | --transpile]");
+    process::exit(2);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut path = None;
+    let mut coverage_file = None;
+    let mut dot = false;
+    let mut decompile = false;
+    let mut transpile = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--coverage" => match args.next() {
+                Some(file) => coverage_file = Some(file),
+                None => usage(),
+            },
+            "--dot" => dot = true,
+            "--decompile" => decompile = true,
+            "--transpile" => transpile = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    let program = intcode::parse(&source);
+    if dot {
+        print!("{}", ControlFlowGraph::build(&program).to_dot());
+        return;
+    }
+    if decompile {
+        print!("{}", intcode::decompile::decompile(&program));
+        return;
+    }
+    if transpile {
+        print!("{}", intcode::transpile::transpile(&program));
+        return;
+    }
+    match coverage_file {
+        None => print!("{}", intcode::disasm::listing(&program)),
+        Some(file) => {
+            let coverage = File::open(&file)
+                .and_then(|file| Coverage::read_from(BufReader::new(file)))
+                .unwrap_or_else(|err| {
+                    eprintln!("{}: {}", file, err);
+                    process::exit(1);
+                });
+            print!("{}", coverage.annotate(&program));
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use intcode::coverage::Coverage;
+use intcode::io::TextInput;
+use intcode::profile::Profiler;
+use intcode::selfmod::SelfModDetector;
+use intcode::{Engine, OutputSink, Program, Status};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+const USAGE: &str = "\
+usage: intcode <program> [options]
+  --input 1,2,3         queue input values, may be repeated
+  --input-file <path>   read further input values from a file, `-` for stdin
+  --patch <addr=value>  write memory before running, may be repeated
+  --read <addr>         print a memory cell after running, may be repeated
+  --max-instructions <n>, --max-outputs <n>, --max-memory <cells>
+                        fail once the program exceeds the limit
+  --timeout <seconds>   fail once the program runs longer than this
+  --coverage <path>     add executed addresses and branches to a coverage file
+  --profile <table|json>
+                        print execution statistics to stderr
+  --self-modification   report writes into the program's own code to stderr
+  --engine <reference|threaded>
+                        interpreter to run the program on, default threaded
+  --format <lines|csv|ascii>
+                        how outputs are printed, default lines";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn count<T: FromStr>(arg: Option<String>) -> T {
+    arg.and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(|| usage())
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Format {
+    Lines,
+    Csv,
+    Ascii,
+}
+
+/// Prints outputs as they are produced.
+struct Printer {
+    format: Format,
+    count: usize,
+}
+
+impl OutputSink for Printer {
+    fn output(&mut self, value: isize) {
+        match self.format {
+            Format::Lines => println!("{}", value),
+            Format::Csv if self.count == 0 => print!("{}", value),
+            Format::Csv => print!(",{}", value),
+            Format::Ascii if (0..128).contains(&value) => print!("{}", value as u8 as char),
+            Format::Ascii => println!("{}", value),
+        }
+        self.count += 1;
+    }
+}
+
+fn run<R: BufRead>(
+    program: &mut Program,
+    mut input: TextInput<R>,
+    printer: &mut Printer,
+) -> Result<(), String> {
+    let result = program.run_with(&mut input, printer);
+    if printer.format == Format::Csv && printer.count > 0 {
+        println!();
+    }
+    if let Some(token) = input.invalid() {
+        return Err(format!("invalid input value `{}`", token));
+    }
+    match result {
+        Ok(Status::NeedsInput) => Err(format!(
+            "waiting for input at {} but none is left",
+            program.cursor()
+        )),
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut path = None;
+    let mut input = Vec::new();
+    let mut input_file = None;
+    let mut patches = Vec::new();
+    let mut reads = Vec::new();
+    let mut format = Format::Lines;
+    let mut max_instructions = None;
+    let mut max_outputs = None;
+    let mut max_memory = None;
+    let mut timeout = None;
+    let mut profile = None;
+    let mut coverage_file = None;
+    let mut selfmod = false;
+    let mut engine = Engine::Threaded;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => match args.next() {
+                Some(values) => input.extend(intcode::parse(&values)),
+                None => usage(),
+            },
+            "--input-file" => match args.next() {
+                Some(file) => input_file = Some(file),
+                None => usage(),
+            },
+            "--patch" => {
+                let patch = args.next().unwrap_or_else(|| usage());
+                match patch.split_once('=').map(|(addr, value)| {
+                    (addr.trim().parse::<isize>(), value.trim().parse::<isize>())
+                }) {
+                    Some((Ok(addr), Ok(value))) if addr >= 0 => patches.push((addr, value)),
+                    _ => usage(),
+                }
+            }
+            "--read" => match args.next().and_then(|addr| addr.parse::<isize>().ok()) {
+                Some(addr) if addr >= 0 => reads.push(addr),
+                _ => usage(),
+            },
+            "--max-instructions" => max_instructions = Some(count(args.next())),
+            "--max-outputs" => max_outputs = Some(count(args.next())),
+            "--max-memory" => max_memory = Some(count(args.next())),
+            "--timeout" => match args
+                .next()
+                .and_then(|secs| secs.parse::<f64>().ok())
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            {
+                Some(duration) => timeout = Some(duration),
+                None => usage(),
+            },
+            "--coverage" => match args.next() {
+                Some(file) => coverage_file = Some(file),
+                None => usage(),
+            },
+            "--profile" => match args.next() {
+                Some(kind) if kind == "table" || kind == "json" => profile = Some(kind),
+                _ => usage(),
+            },
+            "--self-modification" => selfmod = true,
+            "--engine" => {
+                engine = match args.next().as_deref() {
+                    Some("reference") => Engine::Reference,
+                    Some("threaded") => Engine::Threaded,
+                    _ => usage(),
+                }
+            }
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("lines") => Format::Lines,
+                    Some("csv") => Format::Csv,
+                    Some("ascii") => Format::Ascii,
+                    _ => usage(),
+                }
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(2);
+    });
+
+    let mut program = Program::new(&intcode::parse(&source), &input);
+    for (addr, value) in patches {
+        program.write(addr, value).unwrap();
+    }
+    program.set_engine(engine);
+    program.set_instruction_limit(max_instructions);
+    program.set_output_limit(max_outputs);
+    program.set_memory_limit(max_memory);
+    program.set_timeout(timeout);
+    if profile.is_some() {
+        program.set_profiler(Some(Profiler::new()));
+    }
+    if selfmod {
+        program.set_selfmod_detector(Some(SelfModDetector::new()));
+    }
+    if let Some(file) = coverage_file.as_deref() {
+        let coverage = match File::open(file) {
+            Ok(existing) => Coverage::read_from(BufReader::new(existing)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Coverage::new()),
+            Err(err) => Err(err),
+        };
+        match coverage {
+            Ok(coverage) => program.set_coverage(Some(coverage)),
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                process::exit(2);
+            }
+        }
+    }
+    let mut printer = Printer { format, count: 0 };
+    let result = match input_file.as_deref() {
+        None => run(&mut program, TextInput::new(io::empty()), &mut printer),
+        Some("-") => run(&mut program, TextInput::stdin(), &mut printer),
+        Some(file) => match File::open(file) {
+            Ok(file) => run(
+                &mut program,
+                TextInput::new(BufReader::new(file)),
+                &mut printer,
+            ),
+            Err(err) => Err(format!("{}: {}", file, err)),
+        },
+    };
+    if let Some(profiler) = program.profiler() {
+        match profile.as_deref() {
+            Some("json") => eprintln!("{}", profiler.to_json()),
+            _ => eprint!("{}", profiler.table(10)),
+        }
+    }
+    if let Some(detector) = program.selfmod_detector() {
+        for write in detector.writes() {
+            eprintln!("{}", write);
+        }
+    }
+    if let (Some(file), Some(coverage)) = (coverage_file.as_deref(), program.coverage()) {
+        if let Err(err) = File::create(file).and_then(|out| coverage.write_to(out)) {
+            eprintln!("{}: {}", file, err);
+            process::exit(2);
+        }
+    }
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+    for addr in reads {
+        println!("{}", program.read(addr).unwrap());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::disasm;
+use crate::{Instruction, Opcode, ParamMode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Edge {
+    /// Execution continues with the next instruction.
+    Next(usize),
+    /// A conditional jump to an immediate target.
+    Jump(usize),
+}
+
+impl Edge {
+    pub fn target(self) -> usize {
+        match self {
+            Edge::Next(target) | Edge::Jump(target) => target,
+        }
+    }
+}
+
+/// A run of instructions that is only entered at its first address and
+/// only left after its last one.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+    pub edges: Vec<Edge>,
+    /// Ends in a jump through a position or relative parameter, whose
+    /// target is only known at run time.
+    pub indirect: bool,
+}
+
+impl Block {
+    /// The address just past the last instruction.
+    pub fn end(&self) -> usize {
+        self.instructions
+            .last()
+            .map_or(self.start, |(addr, instruction)| addr + instruction.width())
+    }
+
+    pub fn halts(&self) -> bool {
+        self.instructions
+            .last()
+            .is_some_and(|(_, instruction)| instruction.opcode == Opcode::Halt)
+    }
+}
+
+/// The basic blocks reachable from address 0, following jumps with
+/// immediate targets.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ControlFlowGraph {
+    blocks: BTreeMap<usize, Block>,
+}
+
+impl ControlFlowGraph {
+    pub fn build(program: &[isize]) -> Self {
+        Self::from_code(disasm::reachable(program))
+    }
+
+    /// Splits already decoded instructions, keyed by address, into blocks.
+    pub(crate) fn from_code(code: BTreeMap<usize, Instruction>) -> Self {
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        leaders.insert(0);
+        for (&addr, instruction) in &code {
+            if let Opcode::JumpTrue | Opcode::JumpFalse | Opcode::Halt = instruction.opcode {
+                leaders.insert(addr + instruction.width());
+                leaders.extend(
+                    instruction
+                        .successors(addr as isize)
+                        .into_iter()
+                        .filter_map(|target| usize::try_from(target).ok()),
+                );
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in leaders.iter().filter(|addr| code.contains_key(addr)) {
+            let mut instructions = Vec::new();
+            let mut addr = start;
+            let mut edges = Vec::new();
+            let mut indirect = false;
+            while let Some(instruction) = code.get(&addr) {
+                instructions.push((addr, instruction.clone()));
+                let next = addr + instruction.width();
+                match instruction.opcode {
+                    Opcode::Halt => break,
+                    Opcode::JumpTrue | Opcode::JumpFalse => {
+                        for target in instruction.successors(addr as isize) {
+                            // A target outside the decoded code faults at run
+                            // time, so it gets no edge.
+                            let target = match usize::try_from(target) {
+                                Ok(target) if code.contains_key(&target) => target,
+                                _ => continue,
+                            };
+                            let edge = if target == next {
+                                Edge::Next(target)
+                            } else {
+                                Edge::Jump(target)
+                            };
+                            // A jump to the next address is just the fall
+                            // through.
+                            if !edges.contains(&edge) {
+                                edges.push(edge);
+                            }
+                        }
+                        let condition = instruction.params[0];
+                        let never = condition.mode == ParamMode::Immediate
+                            && (condition.value != 0) != (instruction.opcode == Opcode::JumpTrue);
+                        indirect = instruction.params[1].mode != ParamMode::Immediate && !never;
+                        break;
+                    }
+                    _ if leaders.contains(&next) => {
+                        if code.contains_key(&next) {
+                            edges.push(Edge::Next(next));
+                        }
+                        break;
+                    }
+                    _ => addr = next,
+                }
+            }
+            let block = Block {
+                start,
+                instructions,
+                edges,
+                indirect,
+            };
+            blocks.insert(start, block);
+        }
+        ControlFlowGraph { blocks }
+    }
+
+    /// Every block, in address order.
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&Block> {
+        self.blocks.get(&start)
+    }
+
+    /// The graph in Graphviz DOT format. Jumps are drawn bold, blocks
+    /// ending in an indirect jump point at a shared `indirect` node.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph intcode {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+        if self.blocks().any(|block| block.indirect) {
+            writeln!(out, "    indirect [shape=diamond, label=\"?\"];").unwrap();
+        }
+        for block in self.blocks() {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|(addr, instruction)| format!("{}: {}\\l", addr, instruction))
+                .collect();
+            writeln!(out, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+            for edge in &block.edges {
+                let style = match edge {
+                    Edge::Next(_) => "",
+                    Edge::Jump(_) => " [style=bold]",
+                };
+                writeln!(out, "    b{} -> b{}{};", block.start, edge.target(), style).unwrap();
+            }
+            if block.indirect {
+                writeln!(out, "    b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_blocks() {
+        let program = crate::asm::assemble(
+            "IN [x]\nloop: ADD [x], #-1, [x]\nJT [x], #loop\nJF [x], [ret]\nHLT\nx: .data 0\nret: .data 0",
+        )
+        .unwrap();
+        let cfg = ControlFlowGraph::build(&program);
+        let starts: Vec<_> = cfg.blocks().map(|block| block.start).collect();
+        assert_eq!(vec![0, 2, 9, 12], starts);
+
+        assert_eq!(vec![Edge::Next(2)], cfg.block(0).unwrap().edges);
+        let looping = cfg.block(2).unwrap();
+        assert_eq!(2, looping.instructions.len());
+        assert_eq!(vec![Edge::Next(9), Edge::Jump(2)], looping.edges);
+        assert!(cfg.block(9).unwrap().indirect);
+        assert_eq!(vec![Edge::Next(12)], cfg.block(9).unwrap().edges);
+        assert!(cfg.block(12).unwrap().halts());
+
+        assert_eq!(
+            "digraph intcode {
+    node [shape=box, fontname=monospace];
+    indirect [shape=diamond, label=\"?\"];
+    b0 [label=\"0: IN [13]\\l\"];
+    b0 -> b2;
+    b2 [label=\"2: ADD [13], #-1, [13]\\l6: JT [13], #2\\l\"];
+    b2 -> b9;
+    b2 -> b2 [style=bold];
+    b9 [label=\"9: JF [13], [14]\\l\"];
+    b9 -> b12;
+    b9 -> indirect [style=dashed];
+    b12 [label=\"12: HLT\\l\"];
+}
+",
+            cfg.to_dot()
+        );
+    }
+
+    #[test]
+    fn test_invalid_targets() {
+        for program in &["1105,1,-5,99", "1105,1,100,99", "1106,0,6,99,0,0,42"] {
+            let cfg = ControlFlowGraph::build(&crate::parse(program));
+            assert!(cfg.block(0).unwrap().edges.is_empty(), "{}", program);
+        }
+        let cfg = ControlFlowGraph::build(&crate::parse("1005,9,3,99"));
+        assert_eq!(vec![Edge::Next(3)], cfg.block(0).unwrap().edges);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::disasm::{self, Line};
+use crate::{Instruction, Opcode, Program, Status, VmError};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+/// How often one address was executed and, for conditional jumps, which
+/// way they went.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Hits {
+    pub count: u64,
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Executed addresses and branch directions, gathered once attached with
+/// `Program::set_coverage`. Coverage from several runs of the same program
+/// is combined with `merge`, or by attaching the same `Coverage` to each run.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Coverage {
+    hits: BTreeMap<isize, Hits>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hits(&self, addr: isize) -> Hits {
+        self.hits.get(&addr).copied().unwrap_or_default()
+    }
+
+    /// Every executed address, in order.
+    pub fn addresses(&self) -> impl Iterator<Item = (isize, Hits)> + '_ {
+        self.hits.iter().map(|(&addr, &hits)| (addr, hits))
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&addr, hits) in &other.hits {
+            let entry = self.hits.entry(addr).or_default();
+            entry.count += hits.count;
+            entry.taken += hits.taken;
+            entry.not_taken += hits.not_taken;
+        }
+    }
+
+    /// Writes one `addr count taken not_taken` line per executed address.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (addr, hits) in &self.hits {
+            writeln!(
+                writer,
+                "{} {} {} {}",
+                addr, hits.count, hits.taken, hits.not_taken
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Coverage> {
+        let mut coverage = Coverage::new();
+        for line in reader.lines() {
+            let line = line?;
+            let fields: Vec<_> = line.split_whitespace().map(str::parse::<i64>).collect();
+            match fields[..] {
+                [] => continue,
+                [Ok(addr), Ok(count), Ok(taken), Ok(not_taken)]
+                    if count >= 0 && taken >= 0 && not_taken >= 0 =>
+                {
+                    let hits = Hits {
+                        count: count as u64,
+                        taken: taken as u64,
+                        not_taken: not_taken as u64,
+                    };
+                    coverage.hits.insert(addr as isize, hits);
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid coverage line `{}`", line),
+                    ))
+                }
+            }
+        }
+        Ok(coverage)
+    }
+
+    /// Disassembles `program` with hit counts in the margin. Instructions
+    /// that never ran are marked `#####`, conditional jumps also show how
+    /// often they were taken. Code only reached through indirect jumps is
+    /// decoded if it was executed; code that is only valid once the program
+    /// has modified it stays data, but still shows its hit count.
+    pub fn annotate(&self, program: &[isize]) -> String {
+        let mut code: BTreeMap<_, _> = disasm::disassemble(program)
+            .into_iter()
+            .filter(|line| matches!(line, Line::Code { .. }))
+            .map(|line| (line.addr(), line))
+            .collect();
+        for &addr in self.hits.keys() {
+            let addr = addr as usize;
+            if code.contains_key(&addr) || addr >= program.len() {
+                continue;
+            }
+            if let Ok(instruction) = Instruction::decode(&program[addr..], addr as isize) {
+                let width = instruction.width();
+                if addr + width <= program.len() {
+                    let words = program[addr..addr + width].to_vec();
+                    let line = Line::Code {
+                        addr,
+                        instruction,
+                        words,
+                    };
+                    code.insert(addr, line);
+                }
+            }
+        }
+
+        let (mut instructions, mut covered, mut directions, mut branches) = (0, 0, 0, 0);
+        let mut body = String::new();
+        let mut addr = 0;
+        while addr < program.len() {
+            let line = match code.get(&addr) {
+                Some(line) => line,
+                None => {
+                    // Executed cells that only decode once the program has
+                    // modified them are still counted.
+                    let count = self.hits(addr as isize).count;
+                    let margin = if count > 0 {
+                        instructions += 1;
+                        covered += 1;
+                        count.to_string()
+                    } else {
+                        String::new()
+                    };
+                    let value = program[addr];
+                    body += &format!("{:>8}  {}\n", margin, Line::Data { addr, value });
+                    addr += 1;
+                    continue;
+                }
+            };
+            let end = match line {
+                Line::Code { words, .. } => addr + words.len(),
+                Line::Data { .. } => addr + 1,
+            };
+            // Instructions starting inside this one follow it, as in the
+            // disassembly.
+            let overlapping = code.range(addr + 1..end).map(|(_, line)| line);
+            for line in std::iter::once(line).chain(overlapping) {
+                let hits = self.hits(line.addr() as isize);
+                instructions += 1;
+                let margin = if hits.count > 0 {
+                    covered += 1;
+                    hits.count.to_string()
+                } else {
+                    "#####".to_string()
+                };
+                let mut branch = String::new();
+                if let Line::Code { instruction, .. } = line {
+                    if let Opcode::JumpTrue | Opcode::JumpFalse = instruction.opcode {
+                        branches += 2;
+                        directions += (hits.taken > 0) as usize + (hits.not_taken > 0) as usize;
+                        branch = format!("  [taken {}, not taken {}]", hits.taken, hits.not_taken);
+                    }
+                }
+                body += &format!("{:>8}  {}{}\n", margin, line, branch);
+            }
+            addr = end;
+        }
+        format!(
+            "; {}/{} instructions executed, {}/{} branch directions taken\n{}",
+            covered, instructions, directions, branches, body
+        )
+    }
+
+    pub(crate) fn cover(&mut self, program: &mut Program) -> Result<Status, VmError> {
+        let cursor = program.cursor;
+        let taken = match program.opcode() {
+            Ok(opcode @ Opcode::JumpTrue) | Ok(opcode @ Opcode::JumpFalse) => program
+                .param(1)
+                .ok()
+                .map(|condition| (condition != 0) == (opcode == Opcode::JumpTrue)),
+            _ => None,
+        };
+        let result = program.execute_checked();
+        if let Ok(Status::NeedsInput) | Err(_) = result {
+            return result;
+        }
+        let hits = self.hits.entry(cursor).or_default();
+        hits.count += 1;
+        match taken {
+            Some(true) => hits.taken += 1,
+            Some(false) => hits.not_taken += 1,
+            None => {}
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BRANCH: &str = "IN [x]\nJT [x], #pos\nOUT #0\nHLT\npos: OUT #1\nHLT\nx: .data 0";
+
+    fn run(program: &[isize], input: isize, coverage: Coverage) -> Coverage {
+        let mut program = Program::new(program, &[input]);
+        program.set_coverage(Some(coverage));
+        program.run().unwrap();
+        program.take_coverage().unwrap()
+    }
+
+    #[test]
+    fn test_annotate() {
+        let program = crate::asm::assemble(BRANCH).unwrap();
+        let coverage = run(&program, 1, Coverage::new());
+        assert_eq!(
+            vec![
+                "; 4/6 instructions executed, 1/2 branch directions taken",
+                "       1      0  IN [11]                      ; 3,11",
+                "       1      2  JT [11], #8                  ; 1005,11,8  [taken 1, not taken 0]",
+                "   #####      5  OUT #0                       ; 104,0",
+                "   #####      7  HLT                          ; 99",
+                "       1      8  OUT #1                       ; 104,1",
+                "       1     10  HLT                          ; 99",
+                "             11  DATA 0",
+            ],
+            coverage.annotate(&program).lines().collect::<Vec<_>>()
+        );
+
+        let coverage = run(&program, 0, coverage);
+        assert_eq!(
+            Hits {
+                count: 2,
+                taken: 1,
+                not_taken: 1
+            },
+            coverage.hits(2)
+        );
+        assert!(coverage
+            .annotate(&program)
+            .starts_with("; 6/6 instructions executed, 2/2 branch directions taken"));
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let program = crate::parse("1105,104,1,99");
+        let coverage = run(&program, 0, Coverage::new());
+        assert_eq!(
+            vec![
+                "; 3/3 instructions executed, 1/2 branch directions taken",
+                "       1      0  JT #104, #1                  ; 1105,104,1  [taken 1, not taken 0]",
+                "       1      1  OUT #1                       ; 104,1",
+                "       1      3  HLT                          ; 99",
+            ],
+            coverage.annotate(&program).lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_merge_and_files() {
+        let program = crate::asm::assemble(BRANCH).unwrap();
+        let mut coverage = run(&program, 1, Coverage::new());
+        coverage.merge(&run(&program, 0, Coverage::new()));
+        assert_eq!(
+            run(&program, 0, run(&program, 1, Coverage::new())),
+            coverage
+        );
+
+        let mut file = Vec::new();
+        coverage.write_to(&mut file).unwrap();
+        assert_eq!(coverage, Coverage::read_from(&file[..]).unwrap());
+        assert!(Coverage::read_from(&b"1 2 x 0\n"[..]).is_err());
+    }
+}
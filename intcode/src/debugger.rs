@@ -0,0 +1,468 @@
+use crate::watch::{Access, WatchEvent, Watchpoint};
+use crate::{Program, Status, VmError};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Why the debugger handed control back to the user.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(isize),
+    Watchpoint(WatchEvent),
+    NeedsInput,
+    Halted,
+    Error(VmError),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Breakpoint(addr) => write!(f, "breakpoint at {}", addr),
+            Stop::Watchpoint(event) => write!(
+                f,
+                "watchpoint: {} of {} at {} ({} -> {})",
+                match event.access {
+                    Access::Read => "read",
+                    _ => "write",
+                },
+                event.addr,
+                event.cursor,
+                event.old,
+                event.new
+            ),
+            Stop::NeedsInput => write!(f, "waiting for input"),
+            Stop::Halted => write!(f, "halted"),
+            Stop::Error(err) => write!(f, "error: {}", err),
+        }
+    }
+}
+
+const HELP: &str = "\
+break <addr>         set a breakpoint (alias b)
+clear <addr>         clear a breakpoint
+breakpoints          list breakpoints
+watch <addr> [len] [r|w|rw]
+                     stop when memory is accessed, default rw (alias w)
+unwatch <addr> [len] remove a watchpoint
+step [n]             execute n instructions, default 1 (alias s)
+continue             run until a breakpoint, input or halt (alias c)
+back [n]             undo n instructions, default 1 (alias bs)
+lastwrite <addr>     run backward to just before the last write to addr
+rewind               run backward to just before the last output
+regs                 show cursor and relative base (alias r)
+mem <addr> [len]     show memory cells (alias x)
+set <addr> <value>   write a memory cell
+input <values..>     queue input values
+disasm [n]           disassemble n instructions at the cursor (alias d)
+quit                 leave the debugger (alias q)";
+
+/// A breakpoint-aware driver around a `Program`, plus the command language
+/// used by the `debugger` binary.
+pub struct Debugger {
+    program: Program,
+    breakpoints: BTreeSet<isize>,
+    outputs: Vec<isize>,
+    last_stop: Option<Stop>,
+}
+
+impl Debugger {
+    pub fn new(program: Program) -> Self {
+        Self {
+            program,
+            breakpoints: BTreeSet::new(),
+            outputs: Vec::new(),
+            last_stop: None,
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut Program {
+        &mut self.program
+    }
+
+    pub fn last_stop(&self) -> Option<Stop> {
+        self.last_stop
+    }
+
+    /// The last stop, which is forgotten so it is only acted on once.
+    pub fn take_last_stop(&mut self) -> Option<Stop> {
+        self.last_stop.take()
+    }
+
+    /// Returns whether the breakpoint was newly added.
+    pub fn set_breakpoint(&mut self, addr: isize) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    /// Returns whether a breakpoint was set at `addr`.
+    pub fn clear_breakpoint(&mut self, addr: isize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &isize> {
+        self.breakpoints.iter()
+    }
+
+    /// Outputs produced since the last call.
+    pub fn take_outputs(&mut self) -> Vec<isize> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    /// Executes up to `count` instructions, stopping early if the program
+    /// halts, blocks on input, faults or reaches a breakpoint.
+    pub fn step(&mut self, count: usize) -> Stop {
+        let mut stop = Stop::Stepped;
+        for idx in 0..count {
+            if idx > 0 && self.breakpoints.contains(&self.program.cursor()) {
+                stop = Stop::Breakpoint(self.program.cursor());
+                break;
+            }
+            match self.execute_one() {
+                Some(halt) => {
+                    stop = halt;
+                    break;
+                }
+                None => continue,
+            }
+        }
+        self.last_stop = Some(stop);
+        stop
+    }
+
+    /// Runs until a breakpoint is reached. The instruction at the cursor is
+    /// always executed, so continuing from a breakpoint makes progress.
+    pub fn resume(&mut self) -> Stop {
+        let stop = loop {
+            if let Some(stop) = self.execute_one() {
+                break stop;
+            }
+            if self.breakpoints.contains(&self.program.cursor()) {
+                break Stop::Breakpoint(self.program.cursor());
+            }
+        };
+        self.last_stop = Some(stop);
+        stop
+    }
+
+    /// Parses and executes one debugger command, returning the text to show.
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let number = |idx: usize| -> Result<isize, String> {
+            let word = words
+                .get(idx)
+                .ok_or_else(|| format!("`{}` needs an argument", words[0]))?;
+            word.parse::<isize>()
+                .map_err(|_| format!("`{}` is not a number", word))
+        };
+        let optional = |idx: usize, default: isize| match words.get(idx) {
+            Some(_) => number(idx),
+            None => Ok(default),
+        };
+        match words.first().copied().unwrap_or("") {
+            "" => Ok(String::new()),
+            "help" | "h" | "?" => Ok(HELP.to_string()),
+            "break" | "b" => {
+                let addr = number(1)?;
+                self.set_breakpoint(addr);
+                Ok(format!("breakpoint set at {}", addr))
+            }
+            "clear" => {
+                let addr = number(1)?;
+                if self.clear_breakpoint(addr) {
+                    Ok(format!("breakpoint cleared at {}", addr))
+                } else {
+                    Err(format!("no breakpoint at {}", addr))
+                }
+            }
+            "breakpoints" => Ok(self
+                .breakpoints
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "watch" | "w" | "unwatch" => {
+                let addr = number(1)?;
+                let (len, rest) = match words.get(2) {
+                    Some(word) if word.parse::<isize>().is_ok() => (number(2)?, 3),
+                    _ => (1, 2),
+                };
+                let addrs = addr..addr.saturating_add(len.max(1));
+                if words[0] == "unwatch" {
+                    return if self.program.remove_watchpoint(&addrs) {
+                        Ok(format!("watchpoint removed on {:?}", addrs))
+                    } else {
+                        Err(format!("no watchpoint on {:?}", addrs))
+                    };
+                }
+                let access = match words.get(rest).copied() {
+                    Some("r") => Access::Read,
+                    Some("w") => Access::Write,
+                    Some("rw") | None => Access::ReadWrite,
+                    Some(other) => return Err(format!("`{}` is not one of r, w or rw", other)),
+                };
+                self.program
+                    .add_watchpoint(Watchpoint::new(addrs.clone(), access));
+                Ok(format!("watchpoint set on {:?}", addrs))
+            }
+            "step" | "s" => {
+                let count = optional(1, 1)?.max(0) as usize;
+                let stop = self.step(count);
+                Ok(self.report(stop))
+            }
+            "continue" | "c" => {
+                let stop = self.resume();
+                Ok(self.report(stop))
+            }
+            "back" | "bs" | "lastwrite" | "rewind" if self.program.history_len() == 0 => {
+                Err("no history to step back through".to_string())
+            }
+            "back" | "bs" => {
+                let count = optional(1, 1)?.max(0);
+                let undone = (0..count).take_while(|_| self.program.step_back()).count();
+                Ok(format!(
+                    "stepped back {} instruction(s)\n{}",
+                    undone,
+                    self.disassemble(1)
+                ))
+            }
+            "lastwrite" => {
+                let addr = number(1)?;
+                if self.program.run_back_to_write(addr) {
+                    Ok(self.disassemble(1))
+                } else {
+                    Err(format!("no recorded write to {}", addr))
+                }
+            }
+            "rewind" => match self.program.rewind_to_output() {
+                Some(value) => Ok(format!("before output: {}\n{}", value, self.disassemble(1))),
+                None => Err("no recorded output".to_string()),
+            },
+            "regs" | "r" => Ok(self.registers()),
+            "mem" | "x" => {
+                let addr = number(1)?;
+                if addr < 0 {
+                    return Err(format!("negative address {}", addr));
+                }
+                let len = optional(2, 1)?.max(0);
+                Ok((0..len)
+                    .filter_map(|offset| addr.checked_add(offset))
+                    .map(|addr| format!("{:>5}: {}", addr, self.program.peek(addr)))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            "set" => {
+                let addr = number(1)?;
+                if addr < 0 {
+                    return Err(format!("negative address {}", addr));
+                }
+                let value = number(2)?;
+                let hit = self
+                    .program
+                    .poke(addr, value)
+                    .map_err(|err| err.to_string())?;
+                let cell = format!("{:>5}: {}", addr, value);
+                Ok(match hit {
+                    Some(event) => format!("{}\n{}", cell, Stop::Watchpoint(event)),
+                    None => cell,
+                })
+            }
+            "input" | "i" => {
+                for idx in 1..words.len() {
+                    self.program.add_input(number(idx)?);
+                }
+                Ok(format!(
+                    "{} input value(s) queued",
+                    self.program.pending_input()
+                ))
+            }
+            "disasm" | "d" => {
+                let count = optional(1, 1)?.max(0);
+                Ok(self.disassemble(count as usize))
+            }
+            other => Err(format!("unknown command `{}`, try `help`", other)),
+        }
+    }
+
+    /// The cursor, relative base and decoded current instruction.
+    pub fn registers(&self) -> String {
+        format!(
+            "cursor={} relative_base={} input={}\n{}",
+            self.program.cursor(),
+            self.program.relative_base(),
+            self.program.pending_input(),
+            self.disassemble(1)
+        )
+    }
+
+    fn disassemble(&self, count: usize) -> String {
+        let mut next = Some(self.program.cursor());
+        let mut lines = Vec::new();
+        while let Some(addr) = next.filter(|_| lines.len() < count) {
+            let words: Vec<_> = (0..4)
+                .map(|offset| self.program.peek(addr.saturating_add(offset)))
+                .collect();
+            let marker = if self.breakpoints.contains(&addr) {
+                '*'
+            } else {
+                ' '
+            };
+            let width = match crate::Instruction::decode(&words, addr) {
+                Ok(instruction) => {
+                    lines.push(format!("{}{:>5}  {}", marker, addr, instruction));
+                    instruction.width()
+                }
+                Err(_) => {
+                    lines.push(format!("{}{:>5}  DATA {}", marker, addr, words[0]));
+                    1
+                }
+            };
+            // The listing ends at the highest address.
+            next = addr.checked_add(width as isize);
+        }
+        lines.join("\n")
+    }
+
+    fn execute_one(&mut self) -> Option<Stop> {
+        match self.program.step() {
+            Ok(Status::Running) => None,
+            Ok(Status::Output(value)) => {
+                self.outputs.push(value);
+                None
+            }
+            Ok(Status::NeedsInput) => Some(Stop::NeedsInput),
+            Ok(Status::Halted) => Some(Stop::Halted),
+            Ok(Status::Watchpoint(event)) => Some(Stop::Watchpoint(event)),
+            Err(err) => Some(Stop::Error(err)),
+        }
+    }
+
+    fn report(&mut self, stop: Stop) -> String {
+        let mut lines: Vec<_> = self
+            .take_outputs()
+            .iter()
+            .map(|value| format!("output: {}", value))
+            .collect();
+        if stop != Stop::Stepped {
+            lines.push(stop.to_string());
+        }
+        lines.push(self.disassemble(1));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_breakpoints() {
+        let program = crate::asm::assemble(
+            "IN [20]\nloop: OUT [20]\nADD [20], #-1, [20]\nJT [20], #loop\nHLT",
+        )
+        .unwrap();
+        let mut debugger = Debugger::new(Program::new(&program, &[]));
+        assert_eq!(Stop::NeedsInput, debugger.resume());
+        debugger.command("input 2").unwrap();
+        debugger.command("b 4").unwrap();
+        assert_eq!(Stop::Breakpoint(4), debugger.resume());
+        assert_eq!(vec![2], debugger.take_outputs());
+        assert_eq!(Stop::Breakpoint(4), debugger.resume());
+        assert_eq!(vec![1], debugger.take_outputs());
+        debugger.command("set 20 5").unwrap();
+        assert_eq!("   20: 5\n   21: 0", debugger.command("x 20 2").unwrap());
+        assert_eq!(
+            "cursor=4 relative_base=0 input=0\n*    4  ADD [20], #-1, [20]",
+            debugger.command("regs").unwrap()
+        );
+        debugger.command("clear 4").unwrap();
+        assert_eq!(Stop::Stepped, debugger.step(2));
+        assert_eq!(Stop::Halted, debugger.resume());
+        assert!(debugger.command("clear 4").is_err());
+        assert!(debugger.command("jump").is_err());
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let program = crate::parse("1001,9,1,9,1005,9,0,99,0,-3");
+        let mut debugger = Debugger::new(Program::new(&program, &[]));
+        debugger.command("watch 9 w").unwrap();
+        assert_eq!(
+            "watchpoint: write of 9 at 0 (-3 -> -2)\n     4  JT [9], #0",
+            debugger.command("c").unwrap()
+        );
+        debugger.command("unwatch 9").unwrap();
+        assert_eq!(Stop::Halted, debugger.resume());
+        assert_eq!(0, debugger.program().read(9).unwrap());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let program = crate::parse("1001,10,1,10,4,10,1005,10,0,99,-3");
+        let mut program = Program::new(&program, &[]);
+        assert!(Debugger::new(program.clone()).command("back").is_err());
+        program.set_history(Some(100));
+        let mut debugger = Debugger::new(program);
+        assert_eq!(Stop::Halted, debugger.resume());
+        assert_eq!(vec![-2, -1, 0], debugger.take_outputs());
+        assert_eq!(
+            "before output: 0\n     4  OUT [10]",
+            debugger.command("rewind").unwrap()
+        );
+        assert_eq!(
+            "stepped back 2 instruction(s)\n     6  JT [10], #0",
+            debugger.command("back 2").unwrap()
+        );
+        assert_eq!(
+            "     0  ADD [10], #1, [10]",
+            debugger.command("lastwrite 10").unwrap()
+        );
+        assert_eq!(-2, debugger.program().read(10).unwrap());
+        assert!(debugger.command("lastwrite 3").is_err());
+    }
+
+    #[test]
+    fn test_set() {
+        let mut program = Program::new(&crate::parse("4,5,99,0,0,42"), &[]);
+        program.set_history(Some(100));
+        program.set_selfmod_detector(Some(crate::selfmod::SelfModDetector::new()));
+        let mut debugger = Debugger::new(program);
+        assert_eq!(Stop::Stepped, debugger.step(1));
+        debugger.command("watch 0 6 w").unwrap();
+        assert!(debugger.command("watch 0 6 x").is_err());
+        assert_eq!(
+            "    0: 1\nwatchpoint: write of 0 at 2 (4 -> 1)",
+            debugger.command("set 0 1").unwrap()
+        );
+        let writes = debugger.program().selfmod_detector().unwrap().writes();
+        assert_eq!(1, writes.len());
+        debugger.command("back").unwrap();
+        assert_eq!(4, debugger.program().read(0).unwrap());
+        assert_eq!(2, debugger.program().cursor());
+        assert_eq!(
+            format!("{:>5}: 0", isize::MAX),
+            debugger.command(&format!("x {} 5", isize::MAX)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_highest_address() {
+        let program = crate::parse("1105,1,9223372036854775807");
+        let mut debugger = Debugger::new(Program::new(&program, &[]));
+        assert_eq!(Stop::Stepped, debugger.step(1));
+        assert_eq!(
+            format!(
+                "cursor={0} relative_base=0 input=0\n {0}  DATA 0",
+                isize::MAX
+            ),
+            debugger.registers()
+        );
+        assert_eq!(
+            format!(" {}  DATA 0", isize::MAX),
+            debugger.command("disasm 3").unwrap()
+        );
+    }
+}
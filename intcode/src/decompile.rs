@@ -0,0 +1,883 @@
+use crate::cfg::{Block, ControlFlowGraph, Edge};
+use crate::{Instruction, Opcode, Param, ParamMode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// A function: address 0 or the target of a call.
+struct Function {
+    entry: usize,
+    /// Member blocks with the relative base offset since entry when each
+    /// starts, `None` where it is not a constant.
+    blocks: BTreeMap<usize, Option<isize>>,
+    /// Number of parameters callers pass at `rb+1` onwards.
+    params: isize,
+    /// Parameter slots the function writes, which callers read back as
+    /// results.
+    results: BTreeSet<isize>,
+}
+
+/// A call site: storing the return address at `rb+0`, then jumping.
+struct Call {
+    target: usize,
+    /// Index of the instruction storing the return address.
+    ret: usize,
+    /// Instructions from this index on store arguments.
+    args: usize,
+}
+
+/// How a region of code continues once it falls off its end.
+#[derive(Clone, Copy)]
+struct Region {
+    /// Innermost loop as `(header, exit)`.
+    looping: Option<(usize, usize)>,
+    /// Where control goes after the region: the exit of an `if`, the
+    /// header of a loop body.
+    follow: usize,
+}
+
+enum Line {
+    Label(usize),
+    Text(usize, String),
+}
+
+/// Pseudocode lines of one function, with the labels jumped to.
+#[derive(Default)]
+struct Listing {
+    lines: Vec<Line>,
+    gotos: BTreeSet<usize>,
+}
+
+impl Listing {
+    fn push(&mut self, depth: usize, text: &str) {
+        self.lines.push(Line::Text(depth, text.to_string()));
+    }
+
+    fn label(&mut self, addr: usize) {
+        self.lines.push(Line::Label(addr));
+    }
+
+    /// Emits a jump to `target`, structured if it leaves or restarts the
+    /// innermost loop.
+    fn jump(&mut self, depth: usize, target: usize, region: Region) {
+        let text = match region.looping {
+            Some((header, _)) if header == target => "continue".to_string(),
+            Some((_, exit)) if exit == target => "break".to_string(),
+            _ => {
+                self.gotos.insert(target);
+                format!("goto L{}", target)
+            }
+        };
+        self.push(depth, &text);
+    }
+}
+
+/// Lifts a program into structured pseudocode.
+///
+/// Calls are recognized by the usual idiom of storing the return address
+/// at `rb+0` and jumping, functions by an `ARB` on entry and a jump back
+/// through the return address. Inside a function, relative cells become
+/// `argN` and `localN`, and cells from `rb+1` on, where callees find their
+/// arguments, `outN`. Other cells are globals `gN`; instructions whose
+/// operands the program patches before they run read and write `mem[gN]`,
+/// the address the patch stored. Loops and
+/// `if`/`else` are recovered from jumps, anything else is left as `goto`.
+pub fn decompile(program: &[isize]) -> String {
+    let cfg = ControlFlowGraph::from_code(reachable(program));
+    Decompiler::new(&cfg).render()
+}
+
+/// Like `disasm::reachable`, but also follows return addresses stored by
+/// call sites.
+pub(crate) fn reachable(program: &[isize]) -> BTreeMap<usize, Instruction> {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![0isize];
+    while let Some(addr) = pending.pop() {
+        if addr < 0 || addr as usize >= program.len() || code.contains_key(&(addr as usize)) {
+            continue;
+        }
+        let instruction = match Instruction::decode(&program[addr as usize..], addr) {
+            Ok(instruction) => instruction,
+            Err(_) => continue,
+        };
+        pending.extend(instruction.successors(addr));
+        pending.extend(return_address(&instruction));
+        code.insert(addr as usize, instruction);
+    }
+    code
+}
+
+/// The constant an instruction stores at `rb+0`, if it does.
+fn return_address(instruction: &Instruction) -> Option<isize> {
+    let params = &instruction.params;
+    let constant = params.len() == 3
+        && params[0].mode == ParamMode::Immediate
+        && params[1].mode == ParamMode::Immediate
+        && params[2] == relative(0);
+    match instruction.opcode {
+        Opcode::Add if constant => params[0].value.checked_add(params[1].value),
+        Opcode::Mul if constant => params[0].value.checked_mul(params[1].value),
+        _ => None,
+    }
+}
+
+fn relative(value: isize) -> Param {
+    Param {
+        mode: ParamMode::Relative,
+        value,
+    }
+}
+
+/// The target of a jump that is always taken.
+fn unconditional(instruction: &Instruction) -> Option<Param> {
+    let condition = instruction.params.first()?;
+    let taken = match instruction.opcode {
+        Opcode::JumpTrue => condition.value != 0,
+        Opcode::JumpFalse => condition.value == 0,
+        _ => return None,
+    };
+    if condition.mode == ParamMode::Immediate && taken {
+        Some(instruction.params[1])
+    } else {
+        None
+    }
+}
+
+/// The relative cell an instruction writes, if any.
+fn relative_write(instruction: &Instruction) -> Option<isize> {
+    let param = instruction.params[instruction.opcode.write_param()? - 1];
+    match param.mode {
+        ParamMode::Relative => Some(param.value),
+        _ => None,
+    }
+}
+
+fn call(block: &Block) -> Option<Call> {
+    let (_, last) = block.instructions.last()?;
+    let target = unconditional(last).filter(|target| target.mode == ParamMode::Immediate)?;
+    let body = &block.instructions[..block.instructions.len() - 1];
+    let ret = body
+        .iter()
+        .rposition(|(_, instruction)| return_address(instruction) == Some(block.end() as isize))?;
+
+    // Arguments are the stores to `rb+1` onwards right before the jump, as
+    // long as none of them reads or overwrites what an earlier one stored.
+    let mut args = body.len();
+    while args > 0 {
+        let (_, instruction) = &body[args - 1];
+        if args - 1 != ret && relative_write(instruction).map_or(true, |slot| slot <= 0) {
+            break;
+        }
+        args -= 1;
+    }
+    let mut stored = BTreeSet::new();
+    let first = args;
+    for (idx, (_, instruction)) in body.iter().enumerate().skip(first) {
+        if idx == ret {
+            continue;
+        }
+        let slot = relative_write(instruction).unwrap();
+        let reads = instruction.params[..instruction.params.len() - 1]
+            .iter()
+            .any(|param| param.mode == ParamMode::Relative && stored.contains(&param.value));
+        if reads || stored.contains(&slot) {
+            args = idx;
+            stored.clear();
+        }
+        stored.insert(slot);
+    }
+    Some(Call {
+        target: target.value as usize,
+        ret,
+        args,
+    })
+}
+
+/// Operand cells some instruction writes through a position parameter
+/// before the instruction they belong to can run.
+fn patched(cfg: &ControlFlowGraph) -> BTreeSet<usize> {
+    let mut owners = BTreeMap::new();
+    for block in cfg.blocks() {
+        for (addr, instruction) in &block.instructions {
+            owners.insert(*addr, (block.start, instruction.width()));
+        }
+    }
+    let successors = |block: &Block| {
+        let mut successors: Vec<_> = block.edges.iter().map(|edge| edge.target()).collect();
+        successors.extend(call(block).map(|_| block.end()));
+        successors
+    };
+
+    let mut patched = BTreeSet::new();
+    for block in cfg.blocks() {
+        let mut reached: Option<BTreeSet<usize>> = None;
+        for (writer, instruction) in &block.instructions {
+            let param = match instruction.opcode.write_param() {
+                Some(idx) => instruction.params[idx - 1],
+                None => continue,
+            };
+            if param.mode != ParamMode::Position || param.value < 0 {
+                continue;
+            }
+            let cell = param.value as usize;
+            let (addr, owner) = match owners.range(..cell).next_back() {
+                Some((&addr, &(owner, width))) if cell < addr + width => (addr, owner),
+                _ => continue,
+            };
+            let reached = reached.get_or_insert_with(|| {
+                let mut reached = BTreeSet::new();
+                let mut pending = successors(block);
+                while let Some(start) = pending.pop() {
+                    if let Some(next) = cfg.block(start).filter(|_| reached.insert(start)) {
+                        pending.extend(successors(next));
+                    }
+                }
+                reached
+            });
+            if owner == block.start && *writer < addr || reached.contains(&owner) {
+                patched.insert(cell);
+            }
+        }
+    }
+    patched
+}
+
+fn sum(a: String, b: String) -> String {
+    match (a.as_str(), b.parse::<isize>()) {
+        ("0", _) => b,
+        (_, Ok(0)) => a,
+        (_, Ok(n)) if n < 0 => match n.checked_neg() {
+            Some(n) => format!("{} - {}", a, n),
+            None => format!("{} + {}", a, b),
+        },
+        _ => format!("{} + {}", a, b),
+    }
+}
+
+fn product(a: String, b: String) -> String {
+    match (a.as_str(), b.as_str()) {
+        ("1", _) => b,
+        (_, "1") => a,
+        ("-1", _) => format!("-{}", b),
+        (_, "-1") => format!("-{}", a),
+        _ => format!("{} * {}", a, b),
+    }
+}
+
+struct Decompiler<'a> {
+    cfg: &'a ControlFlowGraph,
+    functions: BTreeMap<usize, Function>,
+    /// Operand cells written before their instruction can run.
+    patched: BTreeSet<usize>,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(cfg: &'a ControlFlowGraph) -> Self {
+        let mut entries = BTreeSet::new();
+        entries.insert(0);
+        entries.extend(
+            cfg.blocks()
+                .filter_map(|block| call(block).map(|call| call.target)),
+        );
+
+        let mut decompiler = Decompiler {
+            cfg,
+            functions: BTreeMap::new(),
+            patched: patched(cfg),
+        };
+        for entry in entries.into_iter().filter(|&e| cfg.block(e).is_some()) {
+            let function = decompiler.function(entry);
+            decompiler.functions.insert(entry, function);
+        }
+
+        let mut params = BTreeMap::new();
+        for function in decompiler.functions.values() {
+            for &start in function.blocks.keys() {
+                let block = cfg.block(start).unwrap();
+                if let Some(call) = call(block) {
+                    let count = block.instructions[call.args..block.instructions.len() - 1]
+                        .iter()
+                        .filter_map(|(_, instruction)| relative_write(instruction))
+                        .max()
+                        .unwrap_or(0);
+                    let entry = params.entry(call.target).or_insert(0);
+                    *entry = count.max(*entry);
+                }
+            }
+        }
+        for (entry, count) in params {
+            if let Some(function) = decompiler.functions.get_mut(&entry) {
+                function.params = count;
+            }
+        }
+
+        for function in decompiler.functions.values_mut() {
+            for (&start, &delta) in &function.blocks {
+                let mut delta = delta;
+                for (_, instruction) in &cfg.block(start).unwrap().instructions {
+                    if let (Some(slot), Some(delta)) = (relative_write(instruction), delta) {
+                        match slot.checked_add(delta) {
+                            Some(slot) if (1..=function.params).contains(&slot) => {
+                                function.results.insert(slot);
+                            }
+                            _ => {}
+                        }
+                    }
+                    delta = Self::adjust(delta, instruction);
+                }
+            }
+        }
+        decompiler
+    }
+
+    /// Collects the blocks reachable from `entry` without entering calls,
+    /// tracking the relative base along the way.
+    fn function(&self, entry: usize) -> Function {
+        let mut blocks = BTreeMap::new();
+        let mut pending = vec![(entry, Some(0))];
+        while let Some((start, delta)) = pending.pop() {
+            let block = match self.cfg.block(start) {
+                Some(block) if !blocks.contains_key(&start) => block,
+                _ => continue,
+            };
+            blocks.insert(start, delta);
+            let delta = block
+                .instructions
+                .iter()
+                .fold(delta, |delta, (_, instruction)| {
+                    Self::adjust(delta, instruction)
+                });
+            match call(block) {
+                Some(_) => pending.push((block.end(), delta)),
+                None => pending.extend(block.edges.iter().map(|edge| (edge.target(), delta))),
+            }
+        }
+        Function {
+            entry,
+            blocks,
+            params: 0,
+            results: BTreeSet::new(),
+        }
+    }
+
+    fn adjust(delta: Option<isize>, instruction: &Instruction) -> Option<isize> {
+        match instruction.opcode {
+            Opcode::RelativeBase if instruction.params[0].mode == ParamMode::Immediate => {
+                delta.and_then(|delta| delta.checked_add(instruction.params[0].value))
+            }
+            Opcode::RelativeBase => None,
+            _ => delta,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for function in self.functions.values() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let params: Vec<_> = (1..=function.params).map(|p| format!("arg{}", p)).collect();
+            let results: Vec<_> = function
+                .results
+                .iter()
+                .map(|p| format!("arg{}", p))
+                .collect();
+            let results = match results.len() {
+                0 => String::new(),
+                _ => format!(" -> {}", results.join(", ")),
+            };
+            writeln!(
+                out,
+                "fn {}({}){} {{",
+                self.name(function.entry),
+                params.join(", "),
+                results
+            )
+            .unwrap();
+
+            let mut listing = Listing::default();
+            let region = Region {
+                looping: None,
+                follow: usize::MAX,
+            };
+            let first = *function.blocks.keys().next().unwrap();
+            if first != function.entry {
+                listing.jump(1, function.entry, region);
+            }
+            self.region(function, first, usize::MAX, region, 1, &mut listing);
+            for line in listing.lines {
+                match line {
+                    Line::Label(addr) if listing.gotos.contains(&addr) => {
+                        writeln!(out, "L{}:", addr).unwrap()
+                    }
+                    Line::Label(_) => {}
+                    Line::Text(depth, text) => {
+                        writeln!(out, "{}{}", "    ".repeat(depth), text).unwrap()
+                    }
+                }
+            }
+            writeln!(out, "}}").unwrap();
+        }
+        out
+    }
+
+    fn name(&self, entry: usize) -> String {
+        match entry {
+            0 => "main".to_string(),
+            _ => format!("f{}", entry),
+        }
+    }
+
+    /// Emits the blocks of `function` between `from` and `to`.
+    fn region(
+        &self,
+        function: &Function,
+        from: usize,
+        to: usize,
+        region: Region,
+        depth: usize,
+        out: &mut Listing,
+    ) {
+        let mut cursor = from;
+        let mut falls = false;
+        let mut header = region
+            .looping
+            .map(|(header, _)| header)
+            .filter(|&h| h == from);
+        while let Some((&start, &delta)) = function.blocks.range(cursor..to).next() {
+            let block = self.cfg.block(start).unwrap();
+            if header != Some(start) {
+                let latch = function
+                    .blocks
+                    .range(start..to)
+                    .rev()
+                    .map(|(&latch, _)| self.cfg.block(latch).unwrap())
+                    .find(|latch| {
+                        call(latch).is_none() && latch.edges.contains(&Edge::Jump(start))
+                    });
+                if let Some(latch) = latch {
+                    let exit = latch.end();
+                    let body = Region {
+                        looping: Some((start, exit)),
+                        follow: start,
+                    };
+                    out.label(start);
+                    out.push(depth, "loop {");
+                    self.region(function, start, exit, body, depth + 1, out);
+                    out.push(depth, "}");
+                    cursor = exit;
+                    falls = true;
+                    continue;
+                }
+            }
+            header = None;
+            out.label(start);
+
+            let end = block.end();
+            let site = call(block);
+            let mut delta = delta;
+            let count = block.instructions.len();
+            cursor = end;
+            falls = true;
+            for (idx, (addr, instruction)) in block.instructions.iter().enumerate() {
+                let before = delta;
+                delta = Self::adjust(delta, instruction);
+                let entry = start == function.entry && idx == 0 && function.entry != 0;
+                let leaving = idx + 2 == count && {
+                    let (_, last) = &block.instructions[count - 1];
+                    self.returns(last, delta)
+                };
+                if instruction.opcode == Opcode::RelativeBase && (entry || leaving) {
+                    continue;
+                }
+                if let Some(site) = &site {
+                    if idx == site.ret || idx >= site.args {
+                        if idx + 1 < count {
+                            continue;
+                        }
+                        out.push(depth, &self.call(function, block, site, before));
+                        continue;
+                    }
+                }
+                let jumps = matches!(instruction.opcode, Opcode::JumpTrue | Opcode::JumpFalse);
+                if idx + 1 < count || !jumps {
+                    if instruction.opcode == Opcode::Halt {
+                        falls = false;
+                    }
+                    let statement = self.statement(function, *addr, instruction, before);
+                    out.push(depth, &statement);
+                    continue;
+                }
+
+                // The block ends in a jump.
+                let condition = self.operand(function, *addr, 0, instruction.params[0], before);
+                let when_zero = instruction.opcode == Opcode::JumpFalse;
+                let test = |negate: bool| match when_zero != negate {
+                    true => format!("!{}", condition),
+                    false => condition.clone(),
+                };
+                let target = instruction.params[1];
+                if self.returns(instruction, before) {
+                    if unconditional(instruction).is_some() {
+                        out.push(depth, "return");
+                        falls = false;
+                    } else {
+                        out.push(depth, &format!("if {} {{", test(false)));
+                        out.push(depth + 1, "return");
+                        out.push(depth, "}");
+                    }
+                    continue;
+                }
+                if target.mode != ParamMode::Immediate {
+                    let target = self.operand(function, *addr, 1, target, before);
+                    let goto = format!("goto *{}", target);
+                    if unconditional(instruction).is_some() {
+                        out.push(depth, &goto);
+                        falls = false;
+                    } else {
+                        out.push(depth, &format!("if {} {{", test(false)));
+                        out.push(depth + 1, &goto);
+                        out.push(depth, "}");
+                    }
+                    continue;
+                }
+
+                let target = target.value as usize;
+                if unconditional(instruction).is_some() {
+                    falls = false;
+                    if target == end && end < to {
+                        falls = true;
+                    } else if !(end == to && target == region.follow) {
+                        out.jump(depth, target, region);
+                    }
+                    continue;
+                }
+                if instruction.successors(*addr as isize).len() < 2 {
+                    // A jump that is never taken.
+                    continue;
+                }
+                let body = region
+                    .looping
+                    .is_some_and(|(header, _)| header == region.follow);
+                if body && end == to {
+                    // The last test of a loop body: leaving it is the
+                    // exception.
+                    if target == region.follow {
+                        out.push(depth, &format!("if {} {{", test(true)));
+                        out.jump(depth + 1, to, region);
+                        out.push(depth, "}");
+                        falls = false;
+                        continue;
+                    }
+                }
+                let exits = region
+                    .looping
+                    .is_some_and(|(header, exit)| target == header || target == exit);
+                if !exits && end < target && target <= to {
+                    // Skips over code: an `if`, and an `else` when the
+                    // skipped code ends by jumping past more code.
+                    let other = function
+                        .blocks
+                        .range(end..target)
+                        .last()
+                        .and_then(|(&last, _)| {
+                            let last = self.cfg.block(last).unwrap();
+                            let (_, jump) = last.instructions.last()?;
+                            let after = unconditional(jump)?;
+                            let after = after.value as usize;
+                            let plain =
+                                jump.params[1].mode == ParamMode::Immediate && call(last).is_none();
+                            let leaves = region.looping.is_some_and(|(_, exit)| after == exit);
+                            (plain && !leaves && target < after && after <= to).then_some(after)
+                        });
+                    out.push(depth, &format!("if {} {{", test(true)));
+                    let follow = other.unwrap_or(target);
+                    let inner = Region { follow, ..region };
+                    self.region(function, end, target, inner, depth + 1, out);
+                    if let Some(after) = other {
+                        out.push(depth, "} else {");
+                        self.region(function, target, after, inner, depth + 1, out);
+                    }
+                    out.push(depth, "}");
+                    cursor = follow;
+                    continue;
+                }
+                out.push(depth, &format!("if {} {{", test(false)));
+                out.jump(depth + 1, target, region);
+                out.push(depth, "}");
+            }
+        }
+        if falls && to != region.follow && to != usize::MAX {
+            out.jump(depth, to, region);
+        }
+    }
+
+    /// Whether `instruction` jumps back through the return address.
+    fn returns(&self, instruction: &Instruction, delta: Option<isize>) -> bool {
+        match instruction.opcode {
+            Opcode::JumpTrue | Opcode::JumpFalse => {
+                let target = instruction.params[1];
+                target.mode == ParamMode::Relative
+                    && delta.and_then(|d| d.checked_add(target.value)) == Some(0)
+            }
+            _ => false,
+        }
+    }
+
+    fn call(
+        &self,
+        function: &Function,
+        block: &Block,
+        call: &Call,
+        delta: Option<isize>,
+    ) -> String {
+        let mut stored = BTreeMap::new();
+        let count = block.instructions.len();
+        for (idx, (addr, instruction)) in block.instructions[..count - 1].iter().enumerate() {
+            if idx >= call.args && idx != call.ret {
+                let slot = relative_write(instruction).unwrap();
+                stored.insert(slot, self.expression(function, *addr, instruction, delta));
+            }
+        }
+        let (params, results) = match self.functions.get(&call.target) {
+            Some(callee) => (callee.params, callee.results.clone()),
+            None => (0, BTreeSet::new()),
+        };
+        let args: Vec<_> = (1..=params)
+            .map(|slot| {
+                stored
+                    .remove(&slot)
+                    .unwrap_or_else(|| self.slot(function, slot, delta))
+            })
+            .collect();
+        let mut text = String::new();
+        for (slot, value) in stored {
+            write!(text, "{} = {}; ", self.slot(function, slot, delta), value).unwrap();
+        }
+        let results: Vec<_> = results
+            .iter()
+            .map(|&slot| self.slot(function, slot, delta))
+            .collect();
+        if !results.is_empty() {
+            write!(text, "{} = ", results.join(", ")).unwrap();
+        }
+        write!(text, "{}({})", self.name(call.target), args.join(", ")).unwrap();
+        text
+    }
+
+    fn statement(
+        &self,
+        function: &Function,
+        addr: usize,
+        instruction: &Instruction,
+        delta: Option<isize>,
+    ) -> String {
+        let operand =
+            |idx: usize| self.operand(function, addr, idx, instruction.params[idx], delta);
+        match instruction.opcode {
+            Opcode::Input => format!("{} = input()", operand(0)),
+            Opcode::Output => format!("output({})", operand(0)),
+            Opcode::RelativeBase => match instruction.params[0] {
+                Param {
+                    mode: ParamMode::Immediate,
+                    value,
+                } if value < 0 => match value.checked_neg() {
+                    Some(value) => format!("rb -= {}", value),
+                    None => format!("rb += {}", value),
+                },
+                _ => format!("rb += {}", operand(0)),
+            },
+            Opcode::Halt => "halt".to_string(),
+            Opcode::JumpTrue | Opcode::JumpFalse => unreachable!("jumps end blocks"),
+            _ => format!(
+                "{} = {}",
+                operand(2),
+                self.expression(function, addr, instruction, delta)
+            ),
+        }
+    }
+
+    fn expression(
+        &self,
+        function: &Function,
+        addr: usize,
+        instruction: &Instruction,
+        delta: Option<isize>,
+    ) -> String {
+        let operand =
+            |idx: usize| self.operand(function, addr, idx, instruction.params[idx], delta);
+        let (a, b) = (operand(0), operand(1));
+        match instruction.opcode {
+            Opcode::Add => sum(a, b),
+            Opcode::Mul => product(a, b),
+            Opcode::LessThan => format!("{} < {}", a, b),
+            _ => format!("{} == {}", a, b),
+        }
+    }
+
+    fn operand(
+        &self,
+        function: &Function,
+        addr: usize,
+        idx: usize,
+        param: Param,
+        delta: Option<isize>,
+    ) -> String {
+        let cell = addr + 1 + idx;
+        if self.patched.contains(&cell) {
+            return match param.mode {
+                ParamMode::Immediate => format!("g{}", cell),
+                ParamMode::Position => format!("mem[g{}]", cell),
+                ParamMode::Relative => format!("mem[rb + g{}]", cell),
+            };
+        }
+        match param.mode {
+            ParamMode::Immediate => param.value.to_string(),
+            ParamMode::Position => format!("g{}", param.value),
+            ParamMode::Relative => self.slot(function, param.value, delta),
+        }
+    }
+
+    /// Names the cell at `rb+offset`, `delta` cells past the relative base
+    /// the function was entered with.
+    fn slot(&self, function: &Function, offset: isize, delta: Option<isize>) -> String {
+        let delta = match delta {
+            Some(delta) => delta,
+            None => return format!("mem[rb + {}]", offset),
+        };
+        let slot = match offset.checked_add(delta) {
+            Some(slot) => slot,
+            None => return format!("mem[rb + {}]", offset),
+        };
+        match slot {
+            _ if offset > 0 || offset == 0 && slot != 0 => format!("out{}", offset),
+            0 => "ret".to_string(),
+            _ if slot < 0 => format!("mem[{}]", sum("rb".to_string(), offset.to_string())),
+            _ if slot <= function.params => format!("arg{}", slot),
+            _ => format!("local{}", slot - function.params),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decompile() {
+        let program = crate::asm::assemble(
+            "        ARB #stack
+                     IN [n]
+                     ADD #0, #0, [sum]
+             loop:   LT #0, [n], [more]
+                     JF [more], #done
+                     ADD [n], #0, rb+1
+                     ADD #back, #0, rb+0
+                     JT #1, #square
+             back:   ADD [sum], rb+1, [sum]
+                     EQ [n], #3, [more]
+                     JT [more], #skip
+                     OUT [n]
+                     JT #1, #next
+             skip:   OUT #0
+             next:   ADD [n], #-1, [n]
+                     JT #1, #loop
+             done:   OUT [sum]
+                     HLT
+             square: ARB #2
+                     MUL rb-1, rb-1, rb-1
+                     ARB #-2
+                     JT #1, rb+0
+             n:      .data 0
+             more:   .data 0
+             sum:    .data 0
+             stack:  .data 0",
+        )
+        .unwrap();
+        let mut vm = crate::Program::new(&program, &[4]);
+        vm.run().unwrap();
+        assert_eq!(vec![4, 0, 2, 1, 30], vm.take_output());
+
+        assert_eq!(
+            "fn main() {
+    rb += 68
+    g65 = input()
+    g67 = 0
+    loop {
+        g66 = 0 < g65
+        if !g66 {
+            break
+        }
+        out1 = f54(g65)
+        g67 = g67 + out1
+        g66 = g65 == 3
+        if !g66 {
+            output(g65)
+        } else {
+            output(0)
+        }
+        g65 = g65 - 1
+    }
+    output(g67)
+    halt
+}
+
+fn f54(arg1) -> arg1 {
+    arg1 = arg1 * arg1
+    return
+}
+",
+            decompile(&program)
+        );
+    }
+
+    #[test]
+    fn test_patched_operands() {
+        // Outputs the cell after the one [10] points to by patching the
+        // operand of the `OUT`.
+        let program = crate::parse("1001,10,1,5,4,0,99,0,0,42,8");
+        let mut vm = crate::Program::new(&program, &[]);
+        vm.run().unwrap();
+        assert_eq!(vec![42], vm.take_output());
+        assert_eq!(
+            "fn main() {\n    g5 = g10 + 1\n    output(mem[g5])\n    halt\n}\n",
+            decompile(&program)
+        );
+    }
+
+    #[test]
+    fn test_extreme_values() {
+        let min = isize::MIN;
+        let program = [
+            21101,
+            isize::MAX,
+            1,
+            0,
+            1001,
+            20,
+            min,
+            20,
+            204,
+            min,
+            109,
+            min,
+            204,
+            -1,
+            109,
+            -1,
+            99,
+        ];
+        assert_eq!(
+            "fn main() {
+    ret = 9223372036854775807 + 1
+    g20 = g20 + -9223372036854775808
+    output(mem[rb + -9223372036854775808])
+    rb += -9223372036854775808
+    output(mem[rb + -1])
+    rb -= 1
+    halt
+}
+",
+            decompile(&program)
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::Instruction;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// One line of a listing: either a decoded instruction or a raw data cell.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Line {
+    Code {
+        addr: usize,
+        instruction: Instruction,
+        words: Vec<isize>,
+    },
+    Data {
+        addr: usize,
+        value: isize,
+    },
+}
+
+impl Line {
+    pub fn addr(&self) -> usize {
+        match *self {
+            Line::Code { addr, .. } | Line::Data { addr, .. } => addr,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Code {
+                addr,
+                instruction,
+                words,
+            } => {
+                let words: Vec<_> = words.iter().map(|w| w.to_string()).collect();
+                write!(
+                    f,
+                    "{:>5}  {:<28} ; {}",
+                    addr,
+                    instruction.to_string(),
+                    words.join(",")
+                )
+            }
+            Line::Data { addr, value } => write!(f, "{:>5}  DATA {}", addr, value),
+        }
+    }
+}
+
+/// Disassembles `program` by following control flow from address 0. Only
+/// cells reached as the start of an instruction are decoded, everything
+/// else, including code only reachable through indirect jumps, is `DATA`.
+/// An instruction reached in the middle of another one follows it as a
+/// line of its own.
+pub fn disassemble(program: &[isize]) -> Vec<Line> {
+    let code = reachable(program);
+    let decoded = |addr: usize| {
+        let instruction = code.get(&addr)?;
+        let words = program.get(addr..addr + instruction.width())?.to_vec();
+        Some(Line::Code {
+            addr,
+            instruction: instruction.clone(),
+            words,
+        })
+    };
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < program.len() {
+        match decoded(addr) {
+            Some(line) => {
+                let end = addr + code[&addr].width();
+                lines.push(line);
+                lines.extend((addr + 1..end).filter_map(decoded));
+                addr = end;
+            }
+            _ => {
+                lines.push(Line::Data {
+                    addr,
+                    value: program[addr],
+                });
+                addr += 1;
+            }
+        }
+    }
+    lines
+}
+
+/// The disassembly of `program` as text, one line per instruction or cell.
+pub fn listing(program: &[isize]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+pub(crate) fn reachable(program: &[isize]) -> BTreeMap<usize, Instruction> {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![0isize];
+    while let Some(addr) = pending.pop() {
+        if addr < 0 || addr as usize >= program.len() || code.contains_key(&(addr as usize)) {
+            continue;
+        }
+        let instruction = match Instruction::decode(&program[addr as usize..], addr) {
+            Ok(instruction) => instruction,
+            Err(_) => continue,
+        };
+        pending.extend(instruction.successors(addr));
+        code.insert(addr as usize, instruction);
+    }
+    code
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_listing() {
+        let program = crate::parse("1105,1,7,1,2,3,4,204,-2,99,1,2");
+        let lines: Vec<_> = listing(&program).lines().map(|l| l.to_string()).collect();
+        assert_eq!(
+            vec![
+                "    0  JT #1, #7                    ; 1105,1,7",
+                "    3  DATA 1",
+                "    4  DATA 2",
+                "    5  DATA 3",
+                "    6  DATA 4",
+                "    7  OUT rb-2                     ; 204,-2",
+                "    9  HLT                          ; 99",
+                "   10  DATA 1",
+                "   11  DATA 2",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_undecodable() {
+        let lines = disassemble(&[1, 0, 0, 0, 77, 1]);
+        assert_eq!(Line::Data { addr: 4, value: 77 }, lines[1]);
+        assert_eq!(3, lines.len());
+    }
+
+    #[test]
+    fn test_overlapping() {
+        // The jump lands on the operand of the `JT` itself, which decodes
+        // as an `OUT` of its own.
+        let program = crate::parse("1105,104,1,99");
+        let lines: Vec<_> = listing(&program).lines().map(|l| l.to_string()).collect();
+        assert_eq!(
+            vec![
+                "    0  JT #104, #1                  ; 1105,104,1",
+                "    1  OUT #1                       ; 104,1",
+                "    3  HLT                          ; 99",
+            ],
+            lines
+        );
+    }
+}
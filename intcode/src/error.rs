@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::fmt;
+
+/// A fault raised while executing an instruction. Every variant carries the
+/// cursor (`addr`) and the raw instruction word found there.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum VmError {
+    UnknownOpcode {
+        addr: isize,
+        value: isize,
+    },
+    InvalidParamMode {
+        addr: isize,
+        instruction: isize,
+        param: isize,
+        mode: isize,
+    },
+    WriteToImmediate {
+        addr: isize,
+        instruction: isize,
+        param: isize,
+    },
+    NegativeAddress {
+        addr: isize,
+        instruction: isize,
+        target: isize,
+    },
+    OutOfMemory {
+        addr: isize,
+        instruction: isize,
+        target: isize,
+    },
+    /// An arithmetic result, address or cursor does not fit in an `isize`.
+    Overflow {
+        addr: isize,
+        instruction: isize,
+    },
+    InstructionLimit {
+        addr: isize,
+        instruction: isize,
+    },
+    OutputLimit {
+        addr: isize,
+        instruction: isize,
+    },
+    Timeout {
+        addr: isize,
+        instruction: isize,
+    },
+}
+
+impl VmError {
+    /// The cursor at the time of the fault.
+    pub fn addr(&self) -> isize {
+        match *self {
+            VmError::UnknownOpcode { addr, .. }
+            | VmError::InvalidParamMode { addr, .. }
+            | VmError::WriteToImmediate { addr, .. }
+            | VmError::NegativeAddress { addr, .. }
+            | VmError::OutOfMemory { addr, .. }
+            | VmError::Overflow { addr, .. }
+            | VmError::InstructionLimit { addr, .. }
+            | VmError::OutputLimit { addr, .. }
+            | VmError::Timeout { addr, .. } => addr,
+        }
+    }
+
+    /// The raw instruction word at the cursor.
+    pub fn instruction(&self) -> isize {
+        match *self {
+            VmError::UnknownOpcode { value, .. } => value,
+            VmError::InvalidParamMode { instruction, .. }
+            | VmError::WriteToImmediate { instruction, .. }
+            | VmError::NegativeAddress { instruction, .. }
+            | VmError::OutOfMemory { instruction, .. }
+            | VmError::Overflow { instruction, .. }
+            | VmError::InstructionLimit { instruction, .. }
+            | VmError::OutputLimit { instruction, .. }
+            | VmError::Timeout { instruction, .. } => instruction,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::UnknownOpcode { addr, value } => {
+                write!(f, "unknown opcode {} at {}", value, addr)
+            }
+            VmError::InvalidParamMode {
+                addr,
+                instruction,
+                param,
+                mode,
+            } => write!(
+                f,
+                "invalid mode {} for parameter {} of {} at {}",
+                mode, param, instruction, addr
+            ),
+            VmError::WriteToImmediate {
+                addr,
+                instruction,
+                param,
+            } => write!(
+                f,
+                "parameter {} of {} at {} writes in immediate mode",
+                param, instruction, addr
+            ),
+            VmError::NegativeAddress {
+                addr,
+                instruction,
+                target,
+            } => write!(
+                f,
+                "negative address {} accessed by {} at {}",
+                target, instruction, addr
+            ),
+            VmError::OutOfMemory {
+                addr,
+                instruction,
+                target,
+            } => write!(
+                f,
+                "address {} accessed by {} at {} is out of memory",
+                target, instruction, addr
+            ),
+            VmError::Overflow { addr, instruction } => {
+                write!(f, "arithmetic overflow in {} at {}", instruction, addr)
+            }
+            VmError::InstructionLimit { addr, instruction } => write!(
+                f,
+                "instruction limit reached at {} running {}",
+                addr, instruction
+            ),
+            VmError::OutputLimit { addr, instruction } => write!(
+                f,
+                "output limit reached at {} running {}",
+                addr, instruction
+            ),
+            VmError::Timeout { addr, instruction } => {
+                write!(f, "timed out at {} running {}", addr, instruction)
+            }
+        }
+    }
+}
+
+impl Error for VmError {}
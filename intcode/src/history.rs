@@ -0,0 +1,214 @@
+use crate::{Opcode, Program, Status, VmError};
+use std::collections::VecDeque;
+
+/// Everything needed to undo one executed instruction.
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    cursor: isize,
+    relative_base: isize,
+    last_diag_code: isize,
+    write: Option<(isize, isize)>,
+    input: Option<isize>,
+    output: bool,
+}
+
+/// An undo log of the most recent instructions, bounded by `window`.
+#[derive(Debug, Clone)]
+pub(crate) struct History {
+    window: usize,
+    entries: VecDeque<Undo>,
+}
+
+impl History {
+    pub(crate) fn new(window: usize) -> Self {
+        Self {
+            window,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn push(&mut self, undo: Undo) {
+        if self.window > 0 {
+            if self.entries.len() == self.window {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(undo);
+        }
+    }
+}
+
+/// Executes the instruction at the cursor and logs how to undo it.
+pub(crate) fn record(program: &mut Program, history: &mut History) -> Result<Status, VmError> {
+    let instruction = match program.instruction() {
+        Ok(instruction) => instruction,
+        Err(_) => return program.execute_instruction(),
+    };
+    let write = match instruction.opcode.write_param() {
+        Some(idx) => match program.target(instruction.params[idx - 1]) {
+            Ok(addr) => Some((addr, program.peek(addr))),
+            Err(_) => return program.execute_instruction(),
+        },
+        None => None,
+    };
+    let undo = Undo {
+        cursor: program.cursor,
+        relative_base: program.relative_base,
+        last_diag_code: program.last_diag_code,
+        write,
+        input: match instruction.opcode {
+            Opcode::Input => program.input.front().copied(),
+            _ => None,
+        },
+        output: instruction.opcode == Opcode::Output,
+    };
+    let result = program.execute_instruction();
+    if let Ok(Status::Running) | Ok(Status::Output(_)) = result {
+        history.push(undo);
+    }
+    result
+}
+
+/// Logs a write made from outside any instruction, which `step_back` then
+/// undoes on its own.
+pub(crate) fn record_write(program: &Program, history: &mut History, addr: isize, old: isize) {
+    history.push(Undo {
+        cursor: program.cursor,
+        relative_base: program.relative_base,
+        last_diag_code: program.last_diag_code,
+        write: Some((addr, old)),
+        input: None,
+        output: false,
+    });
+}
+
+impl Program {
+    /// Keeps an undo log of the last `window` instructions so execution can
+    /// be reversed with `step_back`. `None` turns recording off.
+    pub fn set_history(&mut self, window: Option<usize>) {
+        self.history = window.map(|window| Box::new(History::new(window)));
+    }
+
+    /// Number of instructions that can currently be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |history| history.len())
+    }
+
+    /// Undoes the most recent instruction, returning false once the history
+    /// is exhausted.
+    pub fn step_back(&mut self) -> bool {
+        self.undo().is_some()
+    }
+
+    /// Steps back until just before the last recorded write to `addr`.
+    /// Returns false, with the history exhausted, if there was none.
+    pub fn run_back_to_write(&mut self, addr: isize) -> bool {
+        while let Some(undo) = self.undo() {
+            if let Some((target, _)) = undo.write {
+                if target == addr {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Steps back until just before the last recorded output and returns the
+    /// value it produced.
+    pub fn rewind_to_output(&mut self) -> Option<isize> {
+        while let Some(undo) = self.undo() {
+            if undo.output {
+                let value = self.param(1).ok();
+                return value;
+            }
+        }
+        None
+    }
+
+    fn undo(&mut self) -> Option<Undo> {
+        let undo = self.history.as_mut()?.entries.pop_back()?;
+        if let Some((addr, old)) = undo.write {
+            // Only instructions that succeeded are logged, so `addr` is valid.
+            let _ = self.write(addr, old);
+        }
+        if let Some(input) = undo.input {
+            self.input.push_front(input);
+        }
+        self.cursor = undo.cursor;
+        self.relative_base = undo.relative_base;
+        self.last_diag_code = undo.last_diag_code;
+        self.watch_resume = false;
+        Some(undo)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_step_back() {
+        let mut program = Program::new(&crate::parse("3,11,109,4,1001,11,5,11,204,7,99,0"), &[10]);
+        program.set_history(Some(100));
+        assert_eq!(Ok(Status::Halted), program.run());
+        assert_eq!(15, program.diag_code());
+        assert_eq!(4, program.history_len());
+
+        assert_eq!(Some(15), program.rewind_to_output());
+        assert_eq!(8, program.cursor());
+        assert!(program.run_back_to_write(11));
+        assert_eq!(
+            (4, 4, 10),
+            (
+                program.cursor(),
+                program.relative_base(),
+                program.read(11).unwrap()
+            )
+        );
+        assert!(program.run_back_to_write(11));
+        assert_eq!(
+            (0, 0, 0),
+            (
+                program.cursor(),
+                program.relative_base(),
+                program.read(11).unwrap()
+            )
+        );
+        assert!(!program.step_back());
+
+        assert_eq!(Ok(Status::Halted), program.run());
+        assert_eq!(15, program.diag_code());
+    }
+
+    #[test]
+    fn test_window() {
+        let mut program = Program::new(&crate::parse("1001,9,1,9,1005,9,0,99,0,-5"), &[]);
+        program.set_history(Some(3));
+        program.run().unwrap();
+        assert_eq!(3, program.history_len());
+        assert!(program.step_back());
+        assert!(program.step_back());
+        assert!(program.step_back());
+        assert!(!program.step_back());
+        assert_eq!((4, -1), (program.cursor(), program.read(9).unwrap()));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut program =
+            Program::new(&crate::parse("109,1,21101,0,0,9223372036854775807,99"), &[]);
+        program.set_history(Some(3));
+        assert!(matches!(
+            program.run(),
+            Err(VmError::Overflow { addr: 2, .. })
+        ));
+        assert_eq!(1, program.history_len());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::VmError;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Opcode {
+    Add,
+    Mul,
+    Input,
+    Output,
+    JumpTrue,
+    JumpFalse,
+    LessThan,
+    Equal,
+    RelativeBase,
+    Halt,
+}
+
+impl TryFrom<isize> for Opcode {
+    type Error = isize;
+
+    fn try_from(value: isize) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Opcode::Add),
+            2 => Ok(Opcode::Mul),
+            3 => Ok(Opcode::Input),
+            4 => Ok(Opcode::Output),
+            5 => Ok(Opcode::JumpTrue),
+            6 => Ok(Opcode::JumpFalse),
+            7 => Ok(Opcode::LessThan),
+            8 => Ok(Opcode::Equal),
+            9 => Ok(Opcode::RelativeBase),
+            99 => Ok(Opcode::Halt),
+            val => Err(val),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ParamMode {
+    Immediate,
+    Position,
+    Relative,
+}
+
+impl TryFrom<isize> for ParamMode {
+    type Error = isize;
+
+    fn try_from(value: isize) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ParamMode::Position),
+            1 => Ok(ParamMode::Immediate),
+            2 => Ok(ParamMode::Relative),
+            val => Err(val),
+        }
+    }
+}
+
+impl Opcode {
+    /// The opcode in the last two digits of an instruction word. Negative
+    /// words are never instructions and are returned as the error, rather
+    /// than having their digits read modulo 100.
+    pub fn decode(word: isize) -> Result<Opcode, isize> {
+        if word < 0 {
+            return Err(word);
+        }
+        Opcode::try_from(word % 100)
+    }
+
+    /// Number of parameters following the opcode.
+    pub fn arity(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal => 3,
+            Opcode::JumpTrue | Opcode::JumpFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::RelativeBase => 1,
+            Opcode::Halt => 0,
+        }
+    }
+
+    /// The 1-based parameter the instruction writes to, if any.
+    pub fn write_param(self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equal => Some(3),
+            Opcode::Input => Some(1),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Mul => "MUL",
+            Opcode::Input => "IN",
+            Opcode::Output => "OUT",
+            Opcode::JumpTrue => "JT",
+            Opcode::JumpFalse => "JF",
+            Opcode::LessThan => "LT",
+            Opcode::Equal => "EQ",
+            Opcode::RelativeBase => "ARB",
+            Opcode::Halt => "HLT",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        match mnemonic.to_ascii_uppercase().as_str() {
+            "ADD" => Some(Opcode::Add),
+            "MUL" => Some(Opcode::Mul),
+            "IN" => Some(Opcode::Input),
+            "OUT" => Some(Opcode::Output),
+            "JT" => Some(Opcode::JumpTrue),
+            "JF" => Some(Opcode::JumpFalse),
+            "LT" => Some(Opcode::LessThan),
+            "EQ" => Some(Opcode::Equal),
+            "ARB" => Some(Opcode::RelativeBase),
+            "HLT" => Some(Opcode::Halt),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> isize {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Mul => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpTrue => 5,
+            Opcode::JumpFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equal => 8,
+            Opcode::RelativeBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+}
+
+impl ParamMode {
+    /// The mode of the 1-based parameter `param` of an instruction word. An
+    /// invalid mode digit is returned as the error, as is a negative word.
+    pub fn decode(word: isize, param: usize) -> Result<ParamMode, isize> {
+        if word < 0 {
+            return Err(word);
+        }
+        ParamMode::try_from(word / 10isize.pow(param as u32 + 1) % 10)
+    }
+
+    pub fn code(self) -> isize {
+        match self {
+            ParamMode::Position => 0,
+            ParamMode::Immediate => 1,
+            ParamMode::Relative => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ParamMode::Position => "position",
+            ParamMode::Immediate => "immediate",
+            ParamMode::Relative => "relative",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Param {
+    pub mode: ParamMode,
+    pub value: isize,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParamMode::Position => write!(f, "[{}]", self.value),
+            ParamMode::Immediate => write!(f, "#{}", self.value),
+            ParamMode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+            ParamMode::Relative => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+/// A fully decoded instruction: the opcode and its parameters with modes.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub params: Vec<Param>,
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `words`, which are the cells
+    /// from `addr` onwards. Missing trailing cells read as zero.
+    pub fn decode(words: &[isize], addr: isize) -> Result<Instruction, VmError> {
+        let word = |offset: usize| words.get(offset).copied().unwrap_or(0);
+        let instruction = word(0);
+        let opcode = Opcode::decode(instruction).map_err(|_| VmError::UnknownOpcode {
+            addr,
+            value: instruction,
+        })?;
+        let mut params = Vec::with_capacity(opcode.arity());
+        for offset in 1..=opcode.arity() {
+            let mode = ParamMode::decode(instruction, offset).map_err(|mode| {
+                VmError::InvalidParamMode {
+                    addr,
+                    instruction,
+                    param: offset as isize,
+                    mode,
+                }
+            })?;
+            if mode == ParamMode::Immediate && opcode.write_param() == Some(offset) {
+                return Err(VmError::WriteToImmediate {
+                    addr,
+                    instruction,
+                    param: offset as isize,
+                });
+            }
+            params.push(Param {
+                mode,
+                value: word(offset),
+            });
+        }
+        Ok(Instruction { opcode, params })
+    }
+
+    /// Number of cells the instruction occupies.
+    pub fn width(&self) -> usize {
+        1 + self.params.len()
+    }
+
+    /// The instruction encoded back into memory words.
+    pub fn encode(&self) -> Vec<isize> {
+        let modes = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(idx, param)| param.mode.code() * 10isize.pow(idx as u32 + 2))
+            .sum::<isize>();
+        let mut words = vec![self.opcode.code() + modes];
+        words.extend(self.params.iter().map(|param| param.value));
+        words
+    }
+
+    /// Addresses that can execute after this instruction when it lives at
+    /// `addr`, as far as they are known without running the program. Jumps
+    /// through position or relative parameters contribute no target.
+    pub fn successors(&self, addr: isize) -> Vec<isize> {
+        let next = addr + self.width() as isize;
+        match self.opcode {
+            Opcode::Halt => vec![],
+            Opcode::JumpTrue | Opcode::JumpFalse => {
+                let (condition, target) = (self.params[0], self.params[1]);
+                let taken = match condition.mode {
+                    ParamMode::Immediate => {
+                        Some((condition.value != 0) == (self.opcode == Opcode::JumpTrue))
+                    }
+                    _ => None,
+                };
+                let mut successors = Vec::new();
+                if taken != Some(true) {
+                    successors.push(next);
+                }
+                if taken != Some(false) && target.mode == ParamMode::Immediate {
+                    successors.push(target.value);
+                }
+                successors
+            }
+            _ => vec![next],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+        for (idx, param) in self.params.iter().enumerate() {
+            let separator = if idx == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, param)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let instruction = Instruction::decode(&[21001, 9, -3, 7], 0).unwrap();
+        assert_eq!("ADD [9], #-3, rb+7", instruction.to_string());
+        assert_eq!(vec![21001, 9, -3, 7], instruction.encode());
+        assert_eq!(
+            Err(VmError::UnknownOpcode {
+                addr: 5,
+                value: -99
+            }),
+            Instruction::decode(&[-99, 0, 0, 0], 5)
+        );
+        assert_eq!(
+            vec![10],
+            Instruction::decode(&[1105, 1, 10], 0)
+                .unwrap()
+                .successors(0)
+        );
+        assert_eq!(
+            vec![3],
+            Instruction::decode(&[1106, 1, 10], 0)
+                .unwrap()
+                .successors(0)
+        );
+        assert_eq!(
+            vec![3],
+            Instruction::decode(&[5, 1, 10], 0).unwrap().successors(0)
+        );
+    }
+}
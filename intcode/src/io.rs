@@ -0,0 +1,266 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, StdinLock};
+
+/// Where a program's `Input` instructions read from. `None` means nothing is
+/// available yet, which suspends the program with `Status::NeedsInput`.
+pub trait InputSource {
+    fn next_input(&mut self) -> Option<isize>;
+}
+
+/// Where a program's `Output` instructions write to.
+pub trait OutputSink {
+    fn output(&mut self, value: isize);
+}
+
+impl<T: InputSource + ?Sized> InputSource for &mut T {
+    fn next_input(&mut self) -> Option<isize> {
+        (**self).next_input()
+    }
+}
+
+impl<T: OutputSink + ?Sized> OutputSink for &mut T {
+    fn output(&mut self, value: isize) {
+        (**self).output(value)
+    }
+}
+
+impl<T: InputSource + ?Sized> InputSource for Box<T> {
+    fn next_input(&mut self) -> Option<isize> {
+        (**self).next_input()
+    }
+}
+
+impl<T: OutputSink + ?Sized> OutputSink for Box<T> {
+    fn output(&mut self, value: isize) {
+        (**self).output(value)
+    }
+}
+
+impl InputSource for VecDeque<isize> {
+    fn next_input(&mut self) -> Option<isize> {
+        self.pop_front()
+    }
+}
+
+impl OutputSink for VecDeque<isize> {
+    fn output(&mut self, value: isize) {
+        self.push_back(value);
+    }
+}
+
+impl OutputSink for Vec<isize> {
+    fn output(&mut self, value: isize) {
+        self.push(value);
+    }
+}
+
+/// Never has input, so a program reading from it suspends.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoInput;
+
+impl InputSource for NoInput {
+    fn next_input(&mut self) -> Option<isize> {
+        None
+    }
+}
+
+/// Calls the closure every time the program reads, e.g. to steer a paddle
+/// from the current game state.
+pub struct FnInput<F>(pub F);
+
+impl<F: FnMut() -> isize> InputSource for FnInput<F> {
+    fn next_input(&mut self) -> Option<isize> {
+        Some((self.0)())
+    }
+}
+
+/// Reads values from an iterator until it runs out.
+pub struct IterInput<I>(pub I);
+
+impl<I: Iterator<Item = isize>> InputSource for IterInput<I> {
+    fn next_input(&mut self) -> Option<isize> {
+        self.0.next()
+    }
+}
+
+/// Calls the closure with every value the program outputs.
+pub struct FnOutput<F>(pub F);
+
+impl<F: FnMut(isize)> OutputSink for FnOutput<F> {
+    fn output(&mut self, value: isize) {
+        (self.0)(value)
+    }
+}
+
+/// Feeds a string one character code at a time, for programs that speak
+/// ASCII. Text is sent exactly as given so it can arrive in pieces; unlike
+/// `Program::add_ascii`, no newline is added, so end each command with
+/// `\n` yourself.
+#[derive(Debug, Clone)]
+pub struct AsciiInput {
+    bytes: VecDeque<u8>,
+}
+
+impl AsciiInput {
+    pub fn new(text: &str) -> Self {
+        Self {
+            bytes: text.bytes().collect(),
+        }
+    }
+
+    /// Queues more text after whatever has not been read yet.
+    pub fn push_str(&mut self, text: &str) {
+        self.bytes.extend(text.bytes());
+    }
+}
+
+impl InputSource for AsciiInput {
+    fn next_input(&mut self) -> Option<isize> {
+        self.bytes.pop_front().map(isize::from)
+    }
+}
+
+/// Decodes outputs below 128 as ASCII text and keeps anything else, such
+/// as a final answer, as a number.
+#[derive(Debug, Clone, Default)]
+pub struct AsciiOutput {
+    text: String,
+    values: Vec<isize>,
+}
+
+impl AsciiOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn take_text(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+
+    pub fn values(&self) -> &[isize] {
+        &self.values
+    }
+
+    pub fn take_values(&mut self) -> Vec<isize> {
+        std::mem::take(&mut self.values)
+    }
+}
+
+impl OutputSink for AsciiOutput {
+    fn output(&mut self, value: isize) {
+        match value {
+            0..=127 => self.text.push(value as u8 as char),
+            _ => self.values.push(value),
+        }
+    }
+}
+
+/// Parses integers separated by commas or whitespace from a reader, a line
+/// at a time. Input ends at end of file or at the first token that is not a
+/// number, which is kept in `invalid`.
+#[derive(Debug)]
+pub struct TextInput<R> {
+    reader: R,
+    pending: VecDeque<isize>,
+    invalid: Option<String>,
+}
+
+impl<R: BufRead> TextInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            pending: VecDeque::new(),
+            invalid: None,
+        }
+    }
+
+    pub fn invalid(&self) -> Option<&str> {
+        self.invalid.as_deref()
+    }
+}
+
+impl TextInput<StdinLock<'static>> {
+    pub fn stdin() -> Self {
+        Self::new(io::stdin().lock())
+    }
+}
+
+impl<R: BufRead> InputSource for TextInput<R> {
+    fn next_input(&mut self) -> Option<isize> {
+        while self.pending.is_empty() && self.invalid.is_none() {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            }
+            for token in line.split(|c: char| c == ',' || c.is_whitespace()) {
+                if token.is_empty() {
+                    continue;
+                }
+                match token.parse() {
+                    Ok(value) => self.pending.push_back(value),
+                    Err(_) => {
+                        self.invalid = Some(token.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Program, Status};
+
+    #[test]
+    fn test_sources() {
+        let mut text = TextInput::new(&b"1, 2\n\n-3 4x 5\n"[..]);
+        let values: Vec<_> = std::iter::from_fn(|| text.next_input()).collect();
+        assert_eq!(vec![1, 2, -3], values);
+        assert_eq!(Some("4x"), text.invalid());
+
+        let mut ascii = AsciiInput::new("A\n");
+        assert_eq!((Some(65), Some(10), None), {
+            (ascii.next_input(), ascii.next_input(), ascii.next_input())
+        });
+
+        let mut count = 0;
+        let mut counter = FnInput(|| {
+            count += 1;
+            count
+        });
+        assert_eq!(Some(1), counter.next_input());
+        assert_eq!(Some(2), counter.next_input());
+    }
+
+    #[test]
+    fn test_ascii_output() {
+        let mut ascii = AsciiOutput::new();
+        [72, 105, 10, 128, -1].iter().for_each(|&v| ascii.output(v));
+        assert_eq!(("Hi\n", &[128, -1][..]), (ascii.text(), ascii.values()));
+        assert_eq!("Hi\n", ascii.take_text());
+        assert_eq!("", ascii.text());
+    }
+
+    #[test]
+    fn test_run_with() {
+        let echo = crate::parse("3,9,4,9,1105,1,0,99,0,0");
+        let mut program = Program::new(&echo, &[]);
+        let mut output = Vec::new();
+        let status = program.run_with(&mut IterInput(1..=3), &mut output);
+        assert_eq!((Ok(Status::NeedsInput), vec![1, 2, 3]), (status, output));
+
+        let mut seen = Vec::new();
+        let mut sink = FnOutput(|value| seen.push(value * 10));
+        let mut source: Box<dyn InputSource> = Box::new(VecDeque::from(vec![4]));
+        program.run_with(&mut source, &mut sink).unwrap();
+        assert_eq!(vec![40], seen);
+    }
+}
@@ -0,0 +1,749 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+pub mod ascii;
+pub mod asm;
+pub mod cfg;
+pub mod coverage;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+mod error;
+mod history;
+mod instruction;
+pub mod io;
+mod limits;
+mod memory;
+pub mod network;
+mod outputs;
+pub mod profile;
+pub mod scheduler;
+pub mod selfmod;
+mod snapshot;
+mod threaded;
+pub mod trace;
+pub mod transpile;
+pub mod watch;
+
+use coverage::Coverage;
+pub use error::VmError;
+use history::History;
+pub use instruction::{Instruction, Opcode, Param, ParamMode};
+pub use io::{InputSource, OutputSink};
+use limits::Limits;
+pub use memory::{Memory, PAGE_SIZE};
+pub use outputs::{Chunked, Outputs};
+use profile::Profiler;
+use selfmod::SelfModDetector;
+pub use snapshot::Snapshot;
+use threaded::Cache;
+pub use threaded::Engine;
+use trace::Tracer;
+use watch::{WatchEvent, Watchpoint};
+
+/// What happened on the last `step`, or why `run` returned.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Status {
+    Running,
+    NeedsInput,
+    Output(isize),
+    Halted,
+    Watchpoint(WatchEvent),
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    input: VecDeque<isize>,
+    output: Vec<isize>,
+    memory: Memory,
+    memory_limit: Option<usize>,
+    limits: Limits,
+    last_diag_code: isize,
+    cursor: isize,
+    relative_base: isize,
+    tracer: Option<Box<Tracer>>,
+    profiler: Option<Box<Profiler>>,
+    coverage: Option<Box<Coverage>>,
+    selfmod: Option<Box<SelfModDetector>>,
+    watchpoints: Vec<Watchpoint>,
+    watch_resume: bool,
+    history: Option<Box<History>>,
+    cache: Option<Cache>,
+}
+
+impl Program {
+    pub fn new(instructions: &[isize], sequence: &[isize]) -> Self {
+        Self {
+            input: VecDeque::from(sequence.to_vec()),
+            output: Vec::new(),
+            memory: Memory::new(instructions),
+            memory_limit: None,
+            limits: Limits::default(),
+            last_diag_code: 0,
+            cursor: 0,
+            relative_base: 0,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            selfmod: None,
+            watchpoints: Vec::new(),
+            watch_resume: false,
+            history: None,
+            cache: None,
+        }
+    }
+
+    /// Caps the number of memory cells the program may allocate. A write that
+    /// needs a page beyond the limit fails with `VmError::OutOfMemory`.
+    pub fn set_memory_limit(&mut self, cells: Option<usize>) {
+        self.memory_limit = cells;
+    }
+
+    /// Selects the interpreter that executes instructions from now on.
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.cache = match engine {
+            Engine::Reference => None,
+            Engine::Threaded => Some(Cache::new(&self.memory)),
+        };
+    }
+
+    pub fn engine(&self) -> Engine {
+        match self.cache {
+            None => Engine::Reference,
+            Some(_) => Engine::Threaded,
+        }
+    }
+
+    /// Attaches a tracer that records every executed instruction, or
+    /// detaches the current one.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer.map(Box::new);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_deref()
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take().map(|tracer| *tracer)
+    }
+
+    /// Attaches a profiler that gathers execution statistics, or detaches
+    /// the current one.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler.map(Box::new);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take().map(|profiler| *profiler)
+    }
+
+    /// Attaches coverage tracking, or detaches the current one.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        self.coverage = coverage.map(Box::new);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take().map(|coverage| *coverage)
+    }
+
+    /// Attaches a detector for writes into code, or detaches the current one.
+    pub fn set_selfmod_detector(&mut self, detector: Option<SelfModDetector>) {
+        self.selfmod = detector.map(Box::new);
+    }
+
+    pub fn selfmod_detector(&self) -> Option<&SelfModDetector> {
+        self.selfmod.as_deref()
+    }
+
+    pub fn take_selfmod_detector(&mut self) -> Option<SelfModDetector> {
+        self.selfmod.take().map(|detector| *detector)
+    }
+
+    /// Captures the machine state. Memory pages are shared with the running
+    /// program until either side writes, so checkpoints are cheap.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            cursor: self.cursor,
+            relative_base: self.relative_base,
+            input: self.input.iter().copied().collect(),
+            output: self.output.clone(),
+            last_diag_code: self.last_diag_code,
+        }
+    }
+
+    /// Replaces the machine state with `snapshot`. Tracers, watchpoints and
+    /// limits attached to this program are kept, the undo history is not.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory = snapshot.memory.clone();
+        self.cursor = snapshot.cursor;
+        self.relative_base = snapshot.relative_base;
+        self.input = snapshot.input.iter().copied().collect();
+        self.output = snapshot.output.clone();
+        self.last_diag_code = snapshot.last_diag_code;
+        self.watch_resume = false;
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        if self.cache.is_some() {
+            self.cache = Some(Cache::new(&self.memory));
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes every watchpoint on exactly `addrs`, returning whether any was.
+    pub fn remove_watchpoint(&mut self, addrs: &std::ops::Range<isize>) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.addrs() != addrs);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Executes a single instruction. An `Input` with an empty queue leaves
+    /// the cursor where it is and reports `NeedsInput`, so the caller can
+    /// `add_input` and resume. A watchpoint without a callback stops with
+    /// `Watchpoint`; for reads the instruction runs on the next `step`.
+    pub fn step(&mut self) -> Result<Status, VmError> {
+        if self.watchpoints.is_empty() {
+            self.traced_step()
+        } else {
+            watch::step(self)
+        }
+    }
+
+    fn traced_step(&mut self) -> Result<Status, VmError> {
+        match self.tracer.take() {
+            None => self.execute(),
+            Some(mut tracer) => {
+                let result = tracer.trace(self);
+                self.tracer = Some(tracer);
+                result
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<Status, VmError> {
+        self.check_limits()?;
+        let status = match self.profiler.take() {
+            None => self.execute_covered()?,
+            Some(mut profiler) => {
+                let result = profiler.profile(self);
+                self.profiler = Some(profiler);
+                result?
+            }
+        };
+        self.consume_limits(status);
+        Ok(status)
+    }
+
+    fn execute_covered(&mut self) -> Result<Status, VmError> {
+        match self.coverage.take() {
+            None => self.execute_checked(),
+            Some(mut coverage) => {
+                let result = coverage.cover(self);
+                self.coverage = Some(coverage);
+                result
+            }
+        }
+    }
+
+    fn execute_checked(&mut self) -> Result<Status, VmError> {
+        match self.selfmod.take() {
+            None => self.execute_recorded(),
+            Some(mut detector) => {
+                let result = detector.check(self);
+                self.selfmod = Some(detector);
+                result
+            }
+        }
+    }
+
+    fn execute_recorded(&mut self) -> Result<Status, VmError> {
+        match self.history.take() {
+            None => self.execute_instruction(),
+            Some(mut history) => {
+                let result = history::record(self, &mut history);
+                self.history = Some(history);
+                result
+            }
+        }
+    }
+
+    fn execute_instruction(&mut self) -> Result<Status, VmError> {
+        match self.cache {
+            None => self.interpret(),
+            Some(_) => threaded::execute(self),
+        }
+    }
+
+    /// Decodes and executes the instruction at the cursor.
+    fn interpret(&mut self) -> Result<Status, VmError> {
+        let opcode = self.opcode()?;
+        // An instruction reaching past the highest address overflows before
+        // it has any effect.
+        let next = self.add(self.cursor, 1 + opcode.arity() as isize)?;
+        match opcode {
+            Opcode::Add => {
+                let sum = self.add(self.param(1)?, self.param(2)?)?;
+                self.insert(3, sum)?;
+            }
+            Opcode::Mul => {
+                let product = self.mul(self.param(1)?, self.param(2)?)?;
+                self.insert(3, product)?;
+            }
+            Opcode::Input => match self.input.front() {
+                Some(&input) => {
+                    self.insert(1, input)?;
+                    self.input.pop_front();
+                }
+                None => return Ok(Status::NeedsInput),
+            },
+            Opcode::Output => {
+                self.last_diag_code = self.param(1)?;
+                self.cursor = next;
+                return Ok(Status::Output(self.last_diag_code));
+            }
+            Opcode::JumpTrue => {
+                if self.param(1)? != 0 {
+                    self.cursor = self.param(2)?;
+                    return Ok(Status::Running);
+                }
+            }
+            Opcode::JumpFalse => {
+                if self.param(1)? == 0 {
+                    self.cursor = self.param(2)?;
+                    return Ok(Status::Running);
+                }
+            }
+            Opcode::LessThan => {
+                self.insert(3, (self.param(1)? < self.param(2)?).into())?;
+            }
+            Opcode::Equal => {
+                self.insert(3, (self.param(1)? == self.param(2)?).into())?;
+            }
+            Opcode::RelativeBase => {
+                self.relative_base = self.add(self.relative_base, self.param(1)?)?;
+            }
+            Opcode::Halt => return Ok(Status::Halted),
+        };
+        self.cursor = next;
+        Ok(Status::Running)
+    }
+
+    /// Steps until the program halts or blocks on an empty input queue.
+    /// Outputs are kept until collected with `take_output`.
+    pub fn run(&mut self) -> Result<Status, VmError> {
+        let mut output = std::mem::take(&mut self.output);
+        let result = self.run_with(&mut io::NoInput, &mut output);
+        self.output = output;
+        result
+    }
+
+    /// Steps until the program halts or `input` runs dry, reading from
+    /// `input` once the queue is empty and writing every output to `output`.
+    pub fn run_with<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<Status, VmError>
+    where
+        I: InputSource + ?Sized,
+        O: OutputSink + ?Sized,
+    {
+        if self.plain() && self.cache.is_some() {
+            return threaded::run(self, input, output);
+        }
+        loop {
+            match self.step()? {
+                Status::Running => continue,
+                Status::Output(value) => output.output(value),
+                Status::NeedsInput => match input.next_input() {
+                    Some(value) => self.add_input(value),
+                    None => return Ok(Status::NeedsInput),
+                },
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Whether nothing observes or limits execution, so instructions may run
+    /// without going through `step`.
+    fn plain(&self) -> bool {
+        self.tracer.is_none()
+            && self.profiler.is_none()
+            && self.coverage.is_none()
+            && self.selfmod.is_none()
+            && self.history.is_none()
+            && self.watchpoints.is_empty()
+            && self.limits.unlimited()
+    }
+
+    /// Iterates over the values the program outputs, running it lazily.
+    pub fn outputs(&mut self) -> Outputs<'_> {
+        Outputs::new(self)
+    }
+
+    /// The opcode at the cursor, i.e. the instruction the next `step` runs.
+    pub fn opcode(&self) -> Result<Opcode, VmError> {
+        let value = self.load(self.cursor)?;
+        Opcode::decode(value).map_err(|_| VmError::UnknownOpcode {
+            addr: self.cursor,
+            value,
+        })
+    }
+
+    /// The fully decoded instruction at the cursor.
+    pub fn instruction(&self) -> Result<Instruction, VmError> {
+        let words: Vec<_> = (0..4)
+            .map(|offset| self.peek(self.cursor.saturating_add(offset)))
+            .collect();
+        Instruction::decode(&words, self.cursor)
+    }
+
+    pub fn cursor(&self) -> isize {
+        self.cursor
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
+    }
+
+    pub fn add_input(&mut self, input: isize) {
+        self.input.push_back(input);
+    }
+
+    /// Queues `line` as character codes, terminated by a newline unless it
+    /// already ends in one. `io::AsciiInput` sends text as is instead.
+    pub fn add_ascii(&mut self, line: &str) {
+        line.bytes()
+            .for_each(|byte| self.add_input(isize::from(byte)));
+        if !line.ends_with('\n') {
+            self.add_input(10);
+        }
+    }
+
+    /// Number of values queued and not yet consumed by an `Input`.
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Every value output during `run` since the last call.
+    pub fn take_output(&mut self) -> Vec<isize> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// The most recent output.
+    pub fn diag_code(&self) -> isize {
+        self.last_diag_code
+    }
+
+    pub fn read(&self, addr: isize) -> Result<isize, VmError> {
+        self.load(addr)
+    }
+
+    pub fn write(&mut self, addr: isize, value: isize) -> Result<(), VmError> {
+        let cell = self.address(addr)?;
+        self.memory.set(cell, value);
+        if let Some(cache) = self.cache.as_mut() {
+            cache.invalidate(cell);
+        }
+        Ok(())
+    }
+
+    /// Writes `value` to `addr` as if the instruction at the cursor had, so
+    /// the undo history, self-modification detector and watchpoints see it.
+    /// Returns the event of a write watchpoint asking to stop.
+    pub fn poke(&mut self, addr: isize, value: isize) -> Result<Option<WatchEvent>, VmError> {
+        let old = self.load(addr)?;
+        self.store(addr, value)?;
+        if let Some(mut history) = self.history.take() {
+            history::record_write(self, &mut history, addr, old);
+            self.history = Some(history);
+        }
+        if let Some(detector) = self.selfmod.as_mut() {
+            detector.written(self.cursor, addr, old, value);
+        }
+        let event = WatchEvent {
+            access: watch::Access::Write,
+            addr,
+            cursor: self.cursor,
+            old,
+            new: value,
+        };
+        Ok(watch::fire(&self.watchpoints, event))
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    fn param(&self, offset: isize) -> Result<isize, VmError> {
+        let value = self.load(self.add(self.cursor, offset)?)?;
+        match self.param_mode(offset)? {
+            ParamMode::Immediate => Ok(value),
+            ParamMode::Position => self.load(value),
+            ParamMode::Relative => self.load(self.add(self.relative_base, value)?),
+        }
+    }
+
+    fn insert(&mut self, offset: isize, value: isize) -> Result<(), VmError> {
+        let position = match self.param_mode(offset)? {
+            ParamMode::Position => self.load(self.add(self.cursor, offset)?)?,
+            ParamMode::Relative => self.add(
+                self.relative_base,
+                self.load(self.add(self.cursor, offset)?)?,
+            )?,
+            ParamMode::Immediate => {
+                return Err(VmError::WriteToImmediate {
+                    addr: self.cursor,
+                    instruction: self.raw_instruction(),
+                    param: offset,
+                })
+            }
+        };
+        self.store(position, value)
+    }
+
+    /// Writes `value` to `position` on behalf of the instruction at the
+    /// cursor.
+    fn store(&mut self, position: isize, value: isize) -> Result<(), VmError> {
+        let cell = self.address(position)?;
+        if let Some(limit) = self.memory_limit {
+            if self.memory.allocates(cell) && self.memory.peak_usage() + PAGE_SIZE > limit {
+                return Err(VmError::OutOfMemory {
+                    addr: self.cursor,
+                    instruction: self.raw_instruction(),
+                    target: position,
+                });
+            }
+        }
+        self.memory.set(cell, value);
+        if let Some(cache) = self.cache.as_mut() {
+            cache.invalidate(cell);
+        }
+        Ok(())
+    }
+
+    fn param_mode(&self, offset: isize) -> Result<ParamMode, VmError> {
+        let instruction = self.raw_instruction();
+        ParamMode::decode(instruction, offset as usize).map_err(|mode| VmError::InvalidParamMode {
+            addr: self.cursor,
+            instruction,
+            param: offset,
+            mode,
+        })
+    }
+
+    /// `a + b`, failing the instruction at the cursor if it overflows.
+    fn add(&self, a: isize, b: isize) -> Result<isize, VmError> {
+        a.checked_add(b).ok_or_else(|| self.overflow())
+    }
+
+    fn mul(&self, a: isize, b: isize) -> Result<isize, VmError> {
+        a.checked_mul(b).ok_or_else(|| self.overflow())
+    }
+
+    fn overflow(&self) -> VmError {
+        VmError::Overflow {
+            addr: self.cursor,
+            instruction: self.raw_instruction(),
+        }
+    }
+
+    fn load(&self, target: isize) -> Result<isize, VmError> {
+        self.address(target).map(|cell| self.memory.get(cell))
+    }
+
+    fn address(&self, target: isize) -> Result<usize, VmError> {
+        if target < 0 {
+            Err(VmError::NegativeAddress {
+                addr: self.cursor,
+                instruction: self.raw_instruction(),
+                target,
+            })
+        } else {
+            Ok(target as usize)
+        }
+    }
+
+    /// The address `param` names before the instruction runs, which for an
+    /// immediate is its value.
+    fn target(&self, param: Param) -> Result<isize, VmError> {
+        match param.mode {
+            ParamMode::Relative => self.add(self.relative_base, param.value),
+            _ => Ok(param.value),
+        }
+    }
+
+    fn raw_instruction(&self) -> isize {
+        self.peek(self.cursor)
+    }
+
+    /// The value at `addr` for an observer, where a negative address, which
+    /// faults the instruction touching it, reads as 0.
+    pub(crate) fn peek(&self, addr: isize) -> isize {
+        usize::try_from(addr)
+            .map(|cell| self.memory.get(cell))
+            .unwrap_or(0)
+    }
+}
+
+/// Parses a comma separated Intcode program such as the `input.txt` files.
+pub fn parse(input: &str) -> Vec<isize> {
+    input
+        .trim()
+        .split(',')
+        .flat_map(|v| v.parse::<isize>().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_position_and_immediate() {
+        let mut program = Program::new(&parse("1002,4,3,4,33"), &[]);
+        assert_eq!(Ok(Status::Halted), program.run());
+        assert_eq!(99, program.read(4).unwrap());
+    }
+
+    #[test]
+    fn test_jumps() {
+        let input = parse("3,3,1105,-1,9,1101,0,0,12,4,12,99,1");
+        let mut program = Program::new(&input, &[-5]);
+        program.run().unwrap();
+        assert_eq!(1, program.diag_code());
+        let mut program = Program::new(&input, &[0]);
+        program.run().unwrap();
+        assert_eq!(0, program.diag_code());
+    }
+
+    #[test]
+    fn test_relative_base() {
+        let input = parse("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99");
+        let mut program = Program::new(&input, &[]);
+        let mut outputs = Vec::new();
+        loop {
+            match program.step().unwrap() {
+                Status::Output(value) => outputs.push(value),
+                Status::Halted => break,
+                _ => continue,
+            }
+        }
+        assert_eq!(input, outputs);
+    }
+
+    #[test]
+    fn test_input_waits_for_queue() {
+        let mut program = Program::new(&parse("3,5,4,5,99,0"), &[]);
+        assert_eq!(Ok(Status::NeedsInput), program.step());
+        assert_eq!(Ok(Status::NeedsInput), program.run());
+        assert_eq!(Ok(Opcode::Input), program.opcode());
+        program.add_input(42);
+        assert_eq!(Ok(Status::Running), program.step());
+        assert_eq!(Ok(Status::Output(42)), program.step());
+        assert_eq!(Ok(Status::Halted), program.run());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut program = Program::new(&[1, 0, 0, 0, 42], &[]);
+        assert_eq!(Ok(Status::Running), program.step());
+        assert_eq!(
+            Err(VmError::UnknownOpcode { addr: 4, value: 42 }),
+            program.step()
+        );
+        // Negative words are not read modulo 100, -99 is not an `ADD`.
+        assert_eq!(
+            Err(VmError::UnknownOpcode {
+                addr: 0,
+                value: -99
+            }),
+            Program::new(&[-99, 0, 0, 0, 99], &[]).run()
+        );
+        assert_eq!(
+            Err(VmError::UnknownOpcode { addr: 0, value: -1 }),
+            Program::new(&[-1], &[]).run()
+        );
+        assert_eq!(
+            Err(VmError::InvalidParamMode {
+                addr: 0,
+                instruction: 301,
+                param: 1,
+                mode: 3
+            }),
+            Program::new(&[301, 0, 0, 0, 99], &[]).run()
+        );
+        assert_eq!(
+            Err(VmError::WriteToImmediate {
+                addr: 0,
+                instruction: 10001,
+                param: 3
+            }),
+            Program::new(&[10001, 0, 0, 0, 99], &[]).run()
+        );
+        assert_eq!(
+            Err(VmError::NegativeAddress {
+                addr: 0,
+                instruction: 4,
+                target: -1
+            }),
+            Program::new(&[4, -1, 99], &[]).run()
+        );
+        let mut program = Program::new(&[4, -1, 99], &[]);
+        let negative = Err(VmError::NegativeAddress {
+            addr: 0,
+            instruction: 4,
+            target: -1,
+        });
+        assert_eq!(negative, program.write(-1, 7));
+        assert_eq!(negative, program.read(-1).map(|_| ()));
+        for words in [
+            "1101,9223372036854775807,1,0,99",
+            "1102,9223372036854775807,2,0,99",
+            "109,9223372036854775807,109,1,99",
+            "109,1,204,9223372036854775807,99",
+            "1101,104,0,9223372036854775806,1105,1,9223372036854775806",
+        ] {
+            let err = Program::new(&parse(words), &[]).run().unwrap_err();
+            assert!(
+                matches!(err, VmError::Overflow { .. }),
+                "{}: {:?}",
+                words,
+                err
+            );
+        }
+        let mut program = Program::new(&[1101, 1, 1, 5000, 99], &[]);
+        program.set_memory_limit(Some(PAGE_SIZE));
+        assert_eq!(
+            Err(VmError::OutOfMemory {
+                addr: 0,
+                instruction: 1101,
+                target: 5000
+            }),
+            program.run()
+        );
+    }
+
+    #[test]
+    fn test_high_addresses() {
+        let mut program = Program::new(&parse("1101,3,4,1000000,4,1000000,4,2000000,99"), &[]);
+        assert_eq!(Ok(Status::Running), program.step());
+        assert_eq!(Ok(Status::Output(7)), program.step());
+        assert_eq!(Ok(Status::Output(0)), program.step());
+        assert_eq!(2 * PAGE_SIZE, program.memory().peak_usage());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{Opcode, Program, Status, VmError};
+use std::time::{Duration, Instant};
+
+/// How often the clock is read while a timeout is set, in instructions.
+const CLOCK_INTERVAL: u32 = 1024;
+
+/// Remaining budgets for a program, all unlimited by default.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    instructions: Option<u64>,
+    outputs: Option<usize>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    ticks: u32,
+}
+
+impl Limits {
+    pub(crate) fn unlimited(&self) -> bool {
+        self.instructions.is_none() && self.outputs.is_none() && self.timeout.is_none()
+    }
+}
+
+impl Program {
+    /// Allows `count` more instructions before steps fail with
+    /// `VmError::InstructionLimit`.
+    pub fn set_instruction_limit(&mut self, count: Option<u64>) {
+        self.limits.instructions = count;
+    }
+
+    /// Allows `count` more outputs before steps fail with
+    /// `VmError::OutputLimit`.
+    pub fn set_output_limit(&mut self, count: Option<usize>) {
+        self.limits.outputs = count;
+    }
+
+    /// Fails steps with `VmError::Timeout` once `timeout` of wall-clock time
+    /// has passed since the next step. The clock is only read every few
+    /// instructions, so the run may overshoot slightly.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.limits.timeout = timeout;
+        self.limits.deadline = None;
+        self.limits.ticks = 0;
+    }
+
+    /// Fails if running the instruction at the cursor would exceed a limit.
+    pub(crate) fn check_limits(&mut self) -> Result<(), VmError> {
+        let timed_out = match self.limits.timeout {
+            Some(timeout) => {
+                if self.limits.deadline.is_none() {
+                    self.limits.deadline = Instant::now().checked_add(timeout);
+                    // A deadline too far away to represent is never reached.
+                    if self.limits.deadline.is_none() {
+                        self.limits.timeout = None;
+                    }
+                }
+                self.limits.ticks = self.limits.ticks.wrapping_add(1);
+                self.limits.ticks % CLOCK_INTERVAL == 0
+                    && self
+                        .limits
+                        .deadline
+                        .is_some_and(|deadline| Instant::now() >= deadline)
+            }
+            None => false,
+        };
+        let addr = self.cursor;
+        if self.limits.instructions == Some(0) {
+            let instruction = self.peek(addr);
+            Err(VmError::InstructionLimit { addr, instruction })
+        } else if self.limits.outputs == Some(0) && self.opcode() == Ok(Opcode::Output) {
+            let instruction = self.peek(addr);
+            Err(VmError::OutputLimit { addr, instruction })
+        } else if timed_out {
+            let instruction = self.peek(addr);
+            Err(VmError::Timeout { addr, instruction })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Charges an executed instruction against the budgets.
+    pub(crate) fn consume_limits(&mut self, status: Status) {
+        let limits = &mut self.limits;
+        match status {
+            Status::Running => {}
+            Status::Output(_) => {
+                if let Some(outputs) = limits.outputs.as_mut() {
+                    *outputs -= 1;
+                }
+            }
+            _ => return,
+        }
+        if let Some(instructions) = limits.instructions.as_mut() {
+            *instructions -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_instruction_and_output_limits() {
+        let looping = crate::parse("104,7,1105,1,0");
+        let mut program = Program::new(&looping, &[]);
+        program.set_instruction_limit(Some(5));
+        assert_eq!(
+            Err(VmError::InstructionLimit {
+                addr: 2,
+                instruction: 1105
+            }),
+            program.run()
+        );
+        assert_eq!(vec![7, 7, 7], program.take_output());
+
+        let mut program = Program::new(&looping, &[]);
+        program.set_output_limit(Some(2));
+        assert_eq!(
+            Err(VmError::OutputLimit {
+                addr: 0,
+                instruction: 104
+            }),
+            program.run()
+        );
+        program.set_output_limit(None);
+        assert_eq!(Ok(Status::Output(7)), program.step());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut program = Program::new(&crate::parse("1105,1,0"), &[]);
+        program.set_timeout(Some(Duration::from_millis(20)));
+        let err = program.run().unwrap_err();
+        assert_eq!(
+            VmError::Timeout {
+                addr: 0,
+                instruction: 1105
+            },
+            err
+        );
+        assert_eq!("timed out at 0 running 1105", err.to_string());
+
+        let mut program = Program::new(&crate::parse("1101,1,1,0,99"), &[]);
+        program.set_timeout(Some(Duration::MAX));
+        assert_eq!(Ok(Status::Halted), program.run());
+    }
+}
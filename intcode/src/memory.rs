@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const PAGE_SIZE: usize = 1024;
+
+// Pages below this index live in a directly indexed table, anything above it
+// (e.g. scratch space at huge addresses) goes through the hash map.
+const DENSE_PAGES: usize = 4096;
+
+// Pages are shared between clones and copied on their first write, which
+// keeps checkpoints of a running machine cheap.
+type Page = Arc<[isize; PAGE_SIZE]>;
+
+/// Paged Intcode memory. Untouched cells read as zero and pages are only
+/// allocated when a non-zero value is written into them.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    dense: Vec<Option<Page>>,
+    sparse: HashMap<usize, Page>,
+    pages: usize,
+}
+
+impl Memory {
+    pub fn new(data: &[isize]) -> Self {
+        let mut memory = Self::default();
+        for (addr, value) in data.iter().enumerate() {
+            memory.set(addr, *value);
+        }
+        memory
+    }
+
+    pub fn get(&self, addr: usize) -> isize {
+        self.page(addr / PAGE_SIZE)
+            .map(|page| page[addr % PAGE_SIZE])
+            .unwrap_or(0)
+    }
+
+    pub fn set(&mut self, addr: usize, value: isize) {
+        let index = addr / PAGE_SIZE;
+        if value == 0 && self.page(index).is_none() {
+            return;
+        }
+        Arc::make_mut(self.page_mut(index))[addr % PAGE_SIZE] = value;
+    }
+
+    /// Whether writing to `addr` would allocate a new page.
+    pub fn allocates(&self, addr: usize) -> bool {
+        self.page(addr / PAGE_SIZE).is_none()
+    }
+
+    /// Cells backed by allocated pages. Pages are never released, so this
+    /// is also the peak usage over the lifetime of the memory.
+    pub fn peak_usage(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+
+    /// Every non-zero cell, in address order.
+    pub fn cells(&self) -> Vec<(usize, isize)> {
+        let mut indexes: Vec<_> = self
+            .dense
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.is_some())
+            .map(|(index, _)| index)
+            .chain(self.sparse.keys().copied())
+            .collect();
+        indexes.sort_unstable();
+        let mut cells = Vec::new();
+        for index in indexes {
+            let page = self.page(index).unwrap();
+            for (offset, value) in page.iter().enumerate() {
+                if *value != 0 {
+                    cells.push((index * PAGE_SIZE + offset, *value));
+                }
+            }
+        }
+        cells
+    }
+
+    fn page(&self, index: usize) -> Option<&Page> {
+        if index < DENSE_PAGES {
+            self.dense.get(index).and_then(|page| page.as_ref())
+        } else {
+            self.sparse.get(&index)
+        }
+    }
+
+    fn page_mut(&mut self, index: usize) -> &mut Page {
+        let allocated = if index < DENSE_PAGES {
+            if index >= self.dense.len() {
+                self.dense.resize_with(index + 1, || None);
+            }
+            self.dense[index].is_some()
+        } else {
+            self.sparse.contains_key(&index)
+        };
+        if !allocated {
+            self.pages += 1;
+        }
+        if index < DENSE_PAGES {
+            self.dense[index].get_or_insert_with(|| Arc::new([0; PAGE_SIZE]))
+        } else {
+            self.sparse
+                .entry(index)
+                .or_insert_with(|| Arc::new([0; PAGE_SIZE]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sparse_growth() {
+        let mut memory = Memory::new(&[1, 2, 3]);
+        assert_eq!(PAGE_SIZE, memory.peak_usage());
+        assert_eq!(0, memory.get(5_000_000_000));
+        memory.set(5_000_000_000, 7);
+        assert_eq!(7, memory.get(5_000_000_000));
+        memory.set(10 * PAGE_SIZE, 0);
+        assert_eq!(2 * PAGE_SIZE, memory.peak_usage());
+        assert_eq!(3, memory.get(2));
+        assert_eq!(
+            vec![(0, 1), (1, 2), (2, 3), (5_000_000_000, 7)],
+            memory.cells()
+        );
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut memory = Memory::new(&[1, 2, 3]);
+        let checkpoint = memory.clone();
+        memory.set(1, 20);
+        assert_eq!(20, memory.get(1));
+        assert_eq!(2, checkpoint.get(1));
+    }
+}
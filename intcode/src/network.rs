@@ -0,0 +1,330 @@
+use crate::{Program, Status, VmError};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Why a network stopped before every machine halted.
+#[derive(PartialEq, Debug, Clone)]
+pub enum NetworkError {
+    /// A machine faulted, the others were stopped.
+    Vm { machine: usize, error: VmError },
+    /// Every machine still running was waiting on input with nothing in
+    /// flight.
+    Deadlock { blocked: Vec<usize> },
+    /// The designated machine never produced an output.
+    NoOutput { machine: usize },
+    /// A machine was addressed that was never added.
+    UnknownMachine { machine: usize },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Vm { machine, error } => write!(f, "machine {}: {}", machine, error),
+            NetworkError::Deadlock { blocked } => {
+                write!(f, "deadlock: machines {:?} are waiting on input", blocked)
+            }
+            NetworkError::NoOutput { machine } => {
+                write!(f, "machine {} produced no output", machine)
+            }
+            NetworkError::UnknownMachine { machine } => write!(f, "unknown machine {}", machine),
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+enum Message {
+    Value(isize),
+    Stop,
+}
+
+/// State shared by the machine threads, only touched under its lock so the
+/// deadlock check sees a consistent picture.
+struct Monitor {
+    senders: Vec<Sender<Message>>,
+    finished: Vec<bool>,
+    waiting: Vec<bool>,
+    in_flight: usize,
+    error: Option<NetworkError>,
+    /// Set with `error`, polled between steps so a machine that never waits
+    /// on input stops too.
+    stopped: Arc<AtomicBool>,
+}
+
+impl Monitor {
+    fn send(&mut self, to: usize, value: isize) {
+        if !self.finished[to] {
+            self.in_flight += 1;
+            let _ = self.senders[to].send(Message::Value(value));
+        }
+    }
+
+    fn stop(&mut self, error: NetworkError) {
+        if self.error.is_some() {
+            return;
+        }
+        self.error = Some(error);
+        self.stopped.store(true, Ordering::Relaxed);
+        for (sender, finished) in self.senders.iter().zip(&self.finished) {
+            if !finished {
+                let _ = sender.send(Message::Stop);
+            }
+        }
+    }
+
+    fn check_deadlock(&mut self) {
+        let blocked: Vec<_> = (0..self.finished.len())
+            .filter(|&machine| !self.finished[machine])
+            .collect();
+        if !blocked.is_empty()
+            && self.in_flight == 0
+            && blocked.iter().all(|&machine| self.waiting[machine])
+        {
+            self.stop(NetworkError::Deadlock { blocked });
+        }
+    }
+}
+
+/// Intcode machines whose outputs feed other machines' inputs. Each machine
+/// runs on its own thread; an output is delivered to every machine it is
+/// connected to, so chains, rings and fan-out are all declared with
+/// `connect`.
+#[derive(Debug, Default)]
+pub struct Network {
+    machines: Vec<Program>,
+    links: Vec<(usize, usize)>,
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a machine and returns its index. Input already queued on the
+    /// program, such as a phase setting, is consumed first.
+    pub fn add(&mut self, program: Program) -> usize {
+        self.machines.push(program);
+        self.machines.len() - 1
+    }
+
+    /// Sends every output of machine `from` to the input of machine `to`,
+    /// both of which must have been added.
+    pub fn connect(&mut self, from: usize, to: usize) -> Result<(), NetworkError> {
+        for &machine in &[from, to] {
+            self.check(machine)?;
+        }
+        self.links.push((from, to));
+        Ok(())
+    }
+
+    fn check(&self, machine: usize) -> Result<(), NetworkError> {
+        if machine < self.machines.len() {
+            Ok(())
+        } else {
+            Err(NetworkError::UnknownMachine { machine })
+        }
+    }
+
+    /// Runs every machine until all of them halt and returns the last output
+    /// of `machine`.
+    pub fn run(self, machine: usize) -> Result<isize, NetworkError> {
+        self.check(machine)?;
+        let Network { machines, links } = self;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            machines.iter().map(|_| mpsc::channel()).unzip();
+        let monitor = Arc::new(Mutex::new(Monitor {
+            senders,
+            finished: vec![false; machines.len()],
+            waiting: vec![false; machines.len()],
+            in_flight: 0,
+            error: None,
+            stopped: stopped.clone(),
+        }));
+
+        let handles: Vec<_> = machines
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(id, (program, receiver))| {
+                let targets = links
+                    .iter()
+                    .filter(|(from, _)| *from == id)
+                    .map(|(_, to)| *to)
+                    .collect();
+                let monitor = monitor.clone();
+                let stopped = stopped.clone();
+                thread::spawn(move || drive(id, program, receiver, targets, &monitor, &stopped))
+            })
+            .collect();
+        let outputs: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+
+        if let Some(error) = monitor.lock().unwrap().error.take() {
+            return Err(error);
+        }
+        outputs[machine].ok_or(NetworkError::NoOutput { machine })
+    }
+}
+
+/// Runs one machine until it halts or the network is stopped, returning its
+/// last output.
+fn drive(
+    id: usize,
+    mut program: Program,
+    receiver: Receiver<Message>,
+    targets: Vec<usize>,
+    monitor: &Mutex<Monitor>,
+    stopped: &AtomicBool,
+) -> Option<isize> {
+    let mut last = None;
+    while !stopped.load(Ordering::Relaxed) {
+        match program.step() {
+            Ok(Status::Output(value)) => {
+                last = Some(value);
+                let mut monitor = monitor.lock().unwrap();
+                for &to in &targets {
+                    monitor.send(to, value);
+                }
+            }
+            Ok(Status::NeedsInput) => {
+                {
+                    let mut monitor = monitor.lock().unwrap();
+                    monitor.waiting[id] = true;
+                    monitor.check_deadlock();
+                }
+                match receiver.recv() {
+                    Ok(Message::Value(value)) => {
+                        let mut monitor = monitor.lock().unwrap();
+                        monitor.waiting[id] = false;
+                        monitor.in_flight -= 1;
+                        program.add_input(value);
+                    }
+                    Ok(Message::Stop) | Err(_) => break,
+                }
+            }
+            Ok(Status::Halted) => break,
+            Ok(_) => continue,
+            Err(error) => {
+                let mut monitor = monitor.lock().unwrap();
+                monitor.stop(NetworkError::Vm { machine: id, error });
+                break;
+            }
+        }
+    }
+
+    // Values still queued for this machine will never be read.
+    let mut monitor = monitor.lock().unwrap();
+    monitor.finished[id] = true;
+    monitor.waiting[id] = false;
+    while let Ok(message) = receiver.try_recv() {
+        if let Message::Value(_) = message {
+            monitor.in_flight -= 1;
+        }
+    }
+    monitor.check_deadlock();
+    last
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chain_and_ring() {
+        let amplifier = crate::parse("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
+        let mut network = Network::new();
+        for phase in &[4, 3, 2, 1, 0] {
+            network.add(Program::new(&amplifier, &[*phase]));
+        }
+        for idx in 0..4 {
+            network.connect(idx, idx + 1).unwrap();
+        }
+        network.machines[0].add_input(0);
+        assert_eq!(Ok(43210), network.run(4));
+
+        let amplifier = crate::parse(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        );
+        let mut network = Network::new();
+        for phase in &[9, 8, 7, 6, 5] {
+            network.add(Program::new(&amplifier, &[*phase]));
+        }
+        for idx in 0..5 {
+            network.connect(idx, (idx + 1) % 5).unwrap();
+        }
+        network.machines[0].add_input(0);
+        assert_eq!(Ok(139629729), network.run(4));
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let mut network = Network::new();
+        let source = network.add(Program::new(&crate::parse("104,7,99"), &[]));
+        let double = network.add(Program::new(&crate::parse("3,9,1002,9,2,9,4,9,99,0"), &[]));
+        let triple = network.add(Program::new(&crate::parse("3,9,1002,9,3,9,4,9,99,0"), &[]));
+        let sum = network.add(Program::new(
+            &crate::parse("3,11,3,12,1,11,12,11,4,11,99,0,0"),
+            &[],
+        ));
+        network.connect(source, double).unwrap();
+        network.connect(source, triple).unwrap();
+        network.connect(double, sum).unwrap();
+        network.connect(triple, sum).unwrap();
+        assert_eq!(Ok(35), network.run(sum));
+    }
+
+    #[test]
+    fn test_deadlock_and_errors() {
+        let echo = crate::parse("3,0,4,0,99");
+        let mut network = Network::new();
+        let a = network.add(Program::new(&echo, &[]));
+        let b = network.add(Program::new(&echo, &[]));
+        network.connect(a, b).unwrap();
+        network.connect(b, a).unwrap();
+        assert_eq!(
+            Err(NetworkError::Deadlock {
+                blocked: vec![0, 1]
+            }),
+            network.run(b)
+        );
+
+        let mut network = Network::new();
+        let a = network.add(Program::new(&echo, &[]));
+        let b = network.add(Program::new(&crate::parse("104,1,42"), &[]));
+        network.connect(b, a).unwrap();
+        match network.run(a) {
+            Err(NetworkError::Vm { machine: 1, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // A machine spinning without ever waiting on input is stopped too.
+        let mut network = Network::new();
+        network.add(Program::new(&crate::parse("1105,1,0"), &[]));
+        let faulty = network.add(Program::new(&crate::parse("42"), &[]));
+        match network.run(faulty) {
+            Err(NetworkError::Vm { machine: 1, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_machine() {
+        let mut network = Network::new();
+        let a = network.add(Program::new(&crate::parse("99"), &[]));
+        assert_eq!(
+            Err(NetworkError::UnknownMachine { machine: 1 }),
+            network.connect(a, 1)
+        );
+        assert_eq!(
+            Err(NetworkError::UnknownMachine { machine: 2 }),
+            network.run(2)
+        );
+    }
+}
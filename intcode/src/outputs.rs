@@ -0,0 +1,93 @@
+use crate::{Program, Status, VmError};
+
+/// Runs a program lazily, yielding each value it outputs. Iteration ends
+/// when the program halts, blocks on input or stops at a watchpoint, and
+/// after the first error.
+pub struct Outputs<'a> {
+    program: &'a mut Program,
+    done: bool,
+}
+
+impl<'a> Outputs<'a> {
+    pub(crate) fn new(program: &'a mut Program) -> Self {
+        Self {
+            program,
+            done: false,
+        }
+    }
+
+    /// Groups outputs into fixed-size tuples such as `[x, y, tile]`. A
+    /// trailing group cut short by the program stopping is dropped.
+    pub fn chunked<const N: usize>(self) -> Chunked<'a, N> {
+        Chunked { outputs: self }
+    }
+}
+
+impl Iterator for Outputs<'_> {
+    type Item = Result<isize, VmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.program.step() {
+                Ok(Status::Running) => continue,
+                Ok(Status::Output(value)) => return Some(Ok(value)),
+                Ok(_) => self.done = true,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+pub struct Chunked<'a, const N: usize> {
+    outputs: Outputs<'a>,
+}
+
+impl<const N: usize> Iterator for Chunked<'_, N> {
+    type Item = Result<[isize; N], VmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = [0; N];
+        for value in chunk.iter_mut() {
+            *value = match self.outputs.next()? {
+                Ok(value) => value,
+                Err(err) => return Some(Err(err)),
+            };
+        }
+        Some(Ok(chunk))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_outputs() {
+        let mut program = Program::new(&crate::parse("104,1,104,2,3,9,104,3,99,0"), &[]);
+        assert_eq!(vec![Ok(1), Ok(2)], program.outputs().collect::<Vec<_>>());
+        program.add_input(0);
+        assert_eq!(vec![Ok(3)], program.outputs().collect::<Vec<_>>());
+        assert_eq!(None, program.outputs().next());
+    }
+
+    #[test]
+    fn test_chunked() {
+        let mut program = Program::new(&crate::parse("104,1,104,2,104,3,104,4,99"), &[]);
+        let chunks: Vec<_> = program.outputs().chunked::<3>().collect();
+        assert_eq!(vec![Ok([1, 2, 3])], chunks);
+
+        let mut program = Program::new(&crate::parse("104,1,104,2,104,3,104,4,42"), &[]);
+        let mut chunks = program.outputs().chunked::<2>();
+        assert_eq!(Some(Ok([1, 2])), chunks.next());
+        assert_eq!(Some(Ok([3, 4])), chunks.next());
+        assert!(matches!(
+            chunks.next(),
+            Some(Err(VmError::UnknownOpcode { addr: 8, .. }))
+        ));
+        assert_eq!(None, chunks.next());
+    }
+}
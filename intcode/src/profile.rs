@@ -0,0 +1,233 @@
+use crate::{Opcode, ParamMode, Program, Status, VmError};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const MODES: [ParamMode; 3] = [
+    ParamMode::Position,
+    ParamMode::Immediate,
+    ParamMode::Relative,
+];
+
+/// Execution statistics gathered once attached with `Program::set_profiler`:
+/// how often each opcode, parameter mode and address ran, and which memory
+/// cells instructions read and wrote.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    steps: u64,
+    opcodes: HashMap<Opcode, u64>,
+    modes: [u64; 3],
+    addresses: HashMap<isize, u64>,
+    reads: HashMap<isize, u64>,
+    writes: HashMap<isize, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total number of instructions executed.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Executions per opcode, most frequent first.
+    pub fn opcodes(&self) -> Vec<(Opcode, u64)> {
+        let mut opcodes: Vec<_> = self.opcodes.iter().map(|(&op, &n)| (op, n)).collect();
+        opcodes.sort_by_key(|&(op, n)| (std::cmp::Reverse(n), op.code()));
+        opcodes
+    }
+
+    /// Number of parameters decoded in `mode`.
+    pub fn mode(&self, mode: ParamMode) -> u64 {
+        self.modes[mode.code() as usize]
+    }
+
+    /// The `count` most executed instruction addresses, hottest first.
+    pub fn hottest(&self, count: usize) -> Vec<(isize, u64)> {
+        let mut addresses: Vec<_> = self.addresses.iter().map(|(&a, &n)| (a, n)).collect();
+        addresses.sort_by_key(|&(addr, n)| (std::cmp::Reverse(n), addr));
+        addresses.truncate(count);
+        addresses
+    }
+
+    /// Number of distinct addresses an instruction was executed from.
+    pub fn distinct_addresses(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Data reads and writes per memory cell, in address order.
+    pub fn memory(&self) -> Vec<(isize, u64, u64)> {
+        let mut addrs: Vec<_> = self.reads.keys().chain(self.writes.keys()).collect();
+        addrs.sort();
+        addrs.dedup();
+        addrs
+            .into_iter()
+            .map(|addr| {
+                let reads = self.reads.get(addr).copied().unwrap_or(0);
+                let writes = self.writes.get(addr).copied().unwrap_or(0);
+                (*addr, reads, writes)
+            })
+            .collect()
+    }
+
+    /// A readable report, listing `top` entries for addresses and memory.
+    pub fn table(&self, top: usize) -> String {
+        let mut out = String::new();
+        let share = |n: u64| 100.0 * n as f64 / self.steps.max(1) as f64;
+        writeln!(out, "steps               {:>12}", self.steps).unwrap();
+        writeln!(out, "distinct addresses  {:>12}", self.addresses.len()).unwrap();
+        writeln!(out, "\nopcode        count   share").unwrap();
+        for (opcode, n) in self.opcodes() {
+            writeln!(out, "{:<6} {:>12} {:>6.1}%", opcode.mnemonic(), n, share(n)).unwrap();
+        }
+        writeln!(out, "\nmode             count").unwrap();
+        for mode in &MODES {
+            writeln!(out, "{:<9} {:>12}", mode.name(), self.mode(*mode)).unwrap();
+        }
+        writeln!(out, "\naddress       count   share").unwrap();
+        for (addr, n) in self.hottest(top) {
+            writeln!(out, "{:>7} {:>12} {:>6.1}%", addr, n, share(n)).unwrap();
+        }
+        let mut memory = self.memory();
+        memory.sort_by_key(|&(addr, reads, writes)| (std::cmp::Reverse(reads + writes), addr));
+        writeln!(out, "\nmemory        reads       writes").unwrap();
+        for (addr, reads, writes) in memory.into_iter().take(top) {
+            writeln!(out, "{:>7} {:>12} {:>12}", addr, reads, writes).unwrap();
+        }
+        out
+    }
+
+    /// The full statistics as a single JSON object.
+    pub fn to_json(&self) -> String {
+        let opcodes: Vec<_> = self
+            .opcodes()
+            .iter()
+            .map(|(opcode, n)| format!(r#""{}":{}"#, opcode.mnemonic(), n))
+            .collect();
+        let modes: Vec<_> = MODES
+            .iter()
+            .map(|mode| format!(r#""{}":{}"#, mode.name(), self.mode(*mode)))
+            .collect();
+        let addresses: Vec<_> = self
+            .hottest(usize::MAX)
+            .iter()
+            .map(|(addr, n)| format!(r#"{{"addr":{},"count":{}}}"#, addr, n))
+            .collect();
+        let memory: Vec<_> = self
+            .memory()
+            .iter()
+            .map(|(addr, reads, writes)| {
+                format!(
+                    r#"{{"addr":{},"reads":{},"writes":{}}}"#,
+                    addr, reads, writes
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"steps":{},"distinct_addresses":{},"opcodes":{{{}}},"modes":{{{}}},"addresses":[{}],"memory":[{}]}}"#,
+            self.steps,
+            self.addresses.len(),
+            opcodes.join(","),
+            modes.join(","),
+            addresses.join(","),
+            memory.join(",")
+        )
+    }
+
+    pub(crate) fn profile(&mut self, program: &mut Program) -> Result<Status, VmError> {
+        let instruction = match program.instruction() {
+            Ok(instruction) => instruction,
+            Err(_) => return program.execute_covered(),
+        };
+        let cursor = program.cursor;
+        // A jump that is not taken never reads its target.
+        let skipped = match instruction.opcode {
+            Opcode::JumpTrue | Opcode::JumpFalse => match program.param(1) {
+                Ok(condition) if (condition != 0) != (instruction.opcode == Opcode::JumpTrue) => {
+                    Some(1)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        // Resolved before executing, against the relative base the
+        // instruction sees.
+        let accesses: Vec<_> = instruction
+            .params
+            .iter()
+            .enumerate()
+            .filter(|&(idx, param)| param.mode != ParamMode::Immediate && Some(idx) != skipped)
+            .filter_map(|(idx, param)| program.target(*param).ok().map(|addr| (idx, addr)))
+            .collect();
+        let result = program.execute_covered();
+        if let Ok(Status::NeedsInput) | Err(_) = result {
+            return result;
+        }
+
+        self.steps += 1;
+        *self.opcodes.entry(instruction.opcode).or_default() += 1;
+        *self.addresses.entry(cursor).or_default() += 1;
+        for param in &instruction.params {
+            self.modes[param.mode.code() as usize] += 1;
+        }
+        for (idx, addr) in accesses {
+            let counts = if instruction.opcode.write_param() == Some(idx + 1) {
+                &mut self.writes
+            } else {
+                &mut self.reads
+            };
+            *counts.entry(addr).or_default() += 1;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let program = crate::parse("1001,9,1,9,1005,9,0,99,0,-3");
+        let mut program = Program::new(&program, &[]);
+        program.set_profiler(Some(Profiler::new()));
+        program.run().unwrap();
+        let profiler = program.take_profiler().unwrap();
+        assert_eq!(7, profiler.steps());
+        assert_eq!(
+            vec![(Opcode::Add, 3), (Opcode::JumpTrue, 3), (Opcode::Halt, 1)],
+            profiler.opcodes()
+        );
+        assert_eq!(
+            (9, 6),
+            (
+                profiler.mode(ParamMode::Position),
+                profiler.mode(ParamMode::Immediate)
+            )
+        );
+        assert_eq!(vec![(0, 3), (4, 3)], profiler.hottest(2));
+        assert_eq!(3, profiler.distinct_addresses());
+        assert_eq!(vec![(9, 6, 3)], profiler.memory());
+        assert_eq!(
+            r#"{"steps":7,"distinct_addresses":3,"opcodes":{"ADD":3,"JT":3,"HLT":1},"modes":{"position":9,"immediate":6,"relative":0},"addresses":[{"addr":0,"count":3},{"addr":4,"count":3},{"addr":7,"count":1}],"memory":[{"addr":9,"reads":6,"writes":3}]}"#,
+            profiler.to_json()
+        );
+        let table = profiler.table(1);
+        assert!(table.contains("ADD               3   42.9%"));
+        assert!(table.contains("      9            6            3"));
+    }
+
+    #[test]
+    fn test_untaken_jumps() {
+        // Neither the relative target, which would overflow, nor the
+        // negative position target is read.
+        for words in &["109,1,2105,0,9223372036854775807,99", "105,0,-1,99"] {
+            let mut program = Program::new(&crate::parse(words), &[]);
+            program.set_profiler(Some(Profiler::new()));
+            assert_eq!(Ok(Status::Halted), program.run());
+            let profiler = program.take_profiler().unwrap();
+            assert!(profiler.memory().is_empty(), "{}", words);
+        }
+    }
+}
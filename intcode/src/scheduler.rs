@@ -0,0 +1,267 @@
+use crate::network::NetworkError;
+use crate::{Program, Status};
+use std::collections::VecDeque;
+
+/// An output tuple: the destination address followed by its payload.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Packet {
+    pub from: usize,
+    pub to: isize,
+    pub data: Vec<isize>,
+}
+
+/// Why `Scheduler::run` handed control back.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Event {
+    /// A packet addressed to a machine outside the network.
+    Unrouted(Packet),
+    /// Every inbound queue is empty and every machine spent its last turn
+    /// waiting on input.
+    Idle,
+    /// Every machine halted.
+    Halted,
+}
+
+struct Machine {
+    program: Program,
+    inbound: VecDeque<isize>,
+    outbound: Vec<isize>,
+    halted: bool,
+    idle: bool,
+}
+
+/// Runs many machines on the current thread, round-robin in the order they
+/// were added, so every run of the same network is identical. Machine `n`
+/// is addressed as `n`; packets it outputs are queued for their destination.
+/// A machine reading from an empty queue gets the default input and yields.
+pub struct Scheduler {
+    machines: Vec<Machine>,
+    default_input: isize,
+    packet_size: usize,
+    slice: usize,
+    next: usize,
+    events: VecDeque<Event>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            machines: Vec::new(),
+            default_input: -1,
+            packet_size: 3,
+            slice: 1000,
+            next: 0,
+            events: VecDeque::new(),
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value read by a machine whose queue is empty, -1 by default.
+    pub fn with_default_input(mut self, value: isize) -> Self {
+        self.default_input = value;
+        self
+    }
+
+    /// Number of outputs forming one packet, address included. Defaults to 3.
+    pub fn with_packet_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "packets need at least an address");
+        self.packet_size = size;
+        self
+    }
+
+    /// Maximum number of instructions a machine runs per turn.
+    pub fn with_slice(mut self, slice: usize) -> Self {
+        self.slice = slice.max(1);
+        self
+    }
+
+    /// Adds a machine and returns its address.
+    pub fn add(&mut self, program: Program) -> usize {
+        self.machines.push(Machine {
+            program,
+            inbound: VecDeque::new(),
+            outbound: Vec::new(),
+            halted: false,
+            idle: false,
+        });
+        self.machines.len() - 1
+    }
+
+    /// The machine at `addr`, if one was added there.
+    pub fn machine(&self, addr: usize) -> Option<&Program> {
+        self.machines.get(addr).map(|machine| &machine.program)
+    }
+
+    /// Queues a packet's payload on machine `to`.
+    pub fn send(&mut self, to: usize, data: &[isize]) -> Result<(), NetworkError> {
+        match self.machines.get_mut(to) {
+            Some(machine) => {
+                machine.inbound.extend(data);
+                Ok(())
+            }
+            None => Err(NetworkError::UnknownMachine { machine: to }),
+        }
+    }
+
+    /// Runs turns until a packet leaves the network, the network goes idle
+    /// or every machine halts.
+    pub fn run(&mut self) -> Result<Event, NetworkError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            if self.machines.iter().all(|machine| machine.halted) {
+                return Ok(Event::Halted);
+            }
+            if self.next == 0 && self.is_idle() {
+                // Count a full round from here before reporting idle again.
+                self.machines
+                    .iter_mut()
+                    .for_each(|machine| machine.idle = false);
+                return Ok(Event::Idle);
+            }
+            let addr = self.next;
+            self.next = (self.next + 1) % self.machines.len();
+            self.turn(addr)?;
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.machines.iter().all(|machine| {
+            machine.halted
+                || (machine.idle && machine.inbound.is_empty() && machine.outbound.is_empty())
+        })
+    }
+
+    fn turn(&mut self, addr: usize) -> Result<(), NetworkError> {
+        let mut active = false;
+        self.machines[addr].idle = false;
+        for _ in 0..self.slice {
+            let machine = &mut self.machines[addr];
+            if machine.halted {
+                break;
+            }
+            let status = machine.program.step().map_err(|error| NetworkError::Vm {
+                machine: addr,
+                error,
+            })?;
+            match status {
+                Status::Output(value) => {
+                    active = true;
+                    machine.outbound.push(value);
+                    if machine.outbound.len() == self.packet_size {
+                        let mut data = std::mem::take(&mut machine.outbound);
+                        let to = data.remove(0);
+                        self.route(Packet {
+                            from: addr,
+                            to,
+                            data,
+                        });
+                    }
+                }
+                Status::NeedsInput => match machine.inbound.pop_front() {
+                    Some(value) => {
+                        active = true;
+                        machine.program.add_input(value);
+                    }
+                    None => {
+                        machine.program.add_input(self.default_input);
+                        machine.idle = !active;
+                        break;
+                    }
+                },
+                Status::Halted => machine.halted = true,
+                Status::Running | Status::Watchpoint(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn route(&mut self, packet: Packet) {
+        if packet.to >= 0 && (packet.to as usize) < self.machines.len() {
+            self.machines[packet.to as usize]
+                .inbound
+                .extend(&packet.data);
+        } else {
+            self.events.push_back(Event::Unrouted(packet));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RELAY: &str = "\
+        IN [addr]
+loop:   IN [x]
+        EQ [x], #-1, [t]
+        JT [t], #loop
+        IN [y]
+        ADD [addr], #1, [dest]
+        ADD [y], #1, [y]
+        OUT [dest]
+        OUT [x]
+        OUT [y]
+        JT #1, #loop
+addr:   .data 0
+x:      .data 0
+y:      .data 0
+t:      .data 0
+dest:   .data 0";
+
+    #[test]
+    fn test_routing_and_idle() {
+        let relay = crate::asm::assemble(RELAY).unwrap();
+        let mut scheduler = Scheduler::new().with_slice(5);
+        for addr in 0..3 {
+            scheduler.add(Program::new(&relay, &[addr]));
+        }
+        assert_eq!(Ok(Event::Idle), scheduler.run());
+        scheduler.send(0, &[5, 0]).unwrap();
+        assert_eq!(
+            Err(NetworkError::UnknownMachine { machine: 3 }),
+            scheduler.send(3, &[5, 0])
+        );
+        assert!(scheduler.machine(2).is_some());
+        assert!(scheduler.machine(3).is_none());
+        assert_eq!(
+            Ok(Event::Unrouted(Packet {
+                from: 2,
+                to: 3,
+                data: vec![5, 3]
+            })),
+            scheduler.run()
+        );
+        assert_eq!(Ok(Event::Idle), scheduler.run());
+        assert_eq!(Ok(Event::Idle), scheduler.run());
+    }
+
+    #[test]
+    fn test_halt_and_errors() {
+        let mut scheduler = Scheduler::new().with_packet_size(2);
+        scheduler.add(Program::new(&crate::parse("104,1,104,7,99"), &[]));
+        scheduler.add(Program::new(&crate::parse("3,9,4,9,4,9,99"), &[]));
+        assert_eq!(
+            Ok(Event::Unrouted(Packet {
+                from: 1,
+                to: 7,
+                data: vec![7]
+            })),
+            scheduler.run()
+        );
+        assert_eq!(Ok(Event::Halted), scheduler.run());
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(Program::new(&[42], &[]));
+        match scheduler.run() {
+            Err(NetworkError::Vm { machine: 0, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
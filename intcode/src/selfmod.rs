@@ -0,0 +1,192 @@
+use crate::{Program, Status, VmError};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CodeWriteKind {
+    /// The target had already been executed as part of an instruction.
+    Executed,
+    /// The target was executed as part of an instruction after the write.
+    Decoded,
+}
+
+/// A write into the program's own instruction stream.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct CodeWrite {
+    pub kind: CodeWriteKind,
+    /// Cursor of the instruction that wrote.
+    pub writer: isize,
+    pub target: isize,
+    pub old: isize,
+    pub new: isize,
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {} wrote {} -> {} to {}",
+            match self.kind {
+                CodeWriteKind::Executed => "patch of executed code",
+                CodeWriteKind::Decoded => "code generated",
+            },
+            self.writer,
+            self.old,
+            self.new,
+            self.target
+        )
+    }
+}
+
+/// Flags self-modifying code once attached with
+/// `Program::set_selfmod_detector`. A write is reported either right away,
+/// when it lands on a cell that already ran as part of an instruction, or
+/// once the written cell is executed.
+#[derive(Debug, Clone, Default)]
+pub struct SelfModDetector {
+    executed: HashSet<isize>,
+    pending: HashMap<isize, (isize, isize, isize)>,
+    writes: Vec<CodeWrite>,
+}
+
+impl SelfModDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn writes(&self) -> &[CodeWrite] {
+        &self.writes
+    }
+
+    pub fn take_writes(&mut self) -> Vec<CodeWrite> {
+        std::mem::take(&mut self.writes)
+    }
+
+    /// Whether `addr` has run as part of an instruction, so that a write to
+    /// it would leave a cached decode stale.
+    pub fn executed(&self, addr: isize) -> bool {
+        self.executed.contains(&addr)
+    }
+
+    pub(crate) fn check(&mut self, program: &mut Program) -> Result<Status, VmError> {
+        let instruction = match program.instruction() {
+            Ok(instruction) => instruction,
+            Err(_) => return program.execute_recorded(),
+        };
+        let cursor = program.cursor;
+        let target = match instruction.opcode.write_param() {
+            Some(idx) => match program.target(instruction.params[idx - 1]) {
+                Ok(addr) => Some(addr),
+                Err(_) => return program.execute_recorded(),
+            },
+            None => None,
+        };
+        let old = target.map(|addr| program.peek(addr));
+        let result = program.execute_recorded();
+        if let Ok(Status::NeedsInput) | Err(_) = result {
+            return result;
+        }
+
+        for addr in cursor..cursor + instruction.width() as isize {
+            if let Some((writer, old, new)) = self.pending.remove(&addr) {
+                self.writes.push(CodeWrite {
+                    kind: CodeWriteKind::Decoded,
+                    writer,
+                    target: addr,
+                    old,
+                    new,
+                });
+            }
+            self.executed.insert(addr);
+        }
+        if let (Some(target), Some(old)) = (target, old) {
+            self.written(cursor, target, old, program.peek(target));
+        }
+        result
+    }
+
+    /// Notes that the instruction at `writer` changed `target` from `old`
+    /// to `new`.
+    pub(crate) fn written(&mut self, writer: isize, target: isize, old: isize, new: isize) {
+        if self.executed.contains(&target) {
+            self.writes.push(CodeWrite {
+                kind: CodeWriteKind::Executed,
+                writer,
+                target,
+                old,
+                new,
+            });
+        } else {
+            // Keep the value the code had before the first write.
+            let old = self.pending.get(&target).map_or(old, |&(_, old, _)| old);
+            self.pending.insert(target, (writer, old, new));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn detect(program: &str) -> Vec<CodeWrite> {
+        let mut program = Program::new(&crate::parse(program), &[]);
+        program.set_selfmod_detector(Some(SelfModDetector::new()));
+        program.run().unwrap();
+        program.take_selfmod_detector().unwrap().take_writes()
+    }
+
+    #[test]
+    fn test_patched_code() {
+        let writes = detect("1,9,10,3,2,3,11,0,99,30,40,50");
+        assert_eq!(
+            vec![
+                CodeWrite {
+                    kind: CodeWriteKind::Executed,
+                    writer: 0,
+                    target: 3,
+                    old: 3,
+                    new: 70
+                },
+                CodeWrite {
+                    kind: CodeWriteKind::Executed,
+                    writer: 4,
+                    target: 0,
+                    old: 1,
+                    new: 3500
+                },
+            ],
+            writes
+        );
+        assert_eq!(
+            "patch of executed code at 0 wrote 3 -> 70 to 3",
+            writes[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_generated_code() {
+        let writes = detect("1101,99,0,6,104,5,0,1101,1,1,20,99");
+        assert_eq!(
+            vec![CodeWrite {
+                kind: CodeWriteKind::Decoded,
+                writer: 0,
+                target: 6,
+                old: 0,
+                new: 99
+            }],
+            writes
+        );
+        assert!(detect("1101,1,1,20,99").is_empty());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut program =
+            Program::new(&crate::parse("109,1,21101,0,0,9223372036854775807,99"), &[]);
+        program.set_selfmod_detector(Some(SelfModDetector::new()));
+        assert!(matches!(
+            program.run(),
+            Err(VmError::Overflow { addr: 2, .. })
+        ));
+    }
+}
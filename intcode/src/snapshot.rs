@@ -0,0 +1,204 @@
+use crate::Memory;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"ICS2";
+
+/// The complete state of a machine: memory, registers, queued input, the
+/// outputs not yet collected with `take_output` and the last output. Cloning is cheap because memory pages are shared until one
+/// side writes to them.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub(crate) memory: Memory,
+    pub(crate) cursor: isize,
+    pub(crate) relative_base: isize,
+    pub(crate) input: Vec<isize>,
+    pub(crate) output: Vec<isize>,
+    pub(crate) last_diag_code: isize,
+}
+
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.cursor == other.cursor
+            && self.relative_base == other.relative_base
+            && self.input == other.input
+            && self.output == other.output
+            && self.last_diag_code == other.last_diag_code
+            && self.memory.cells() == other.memory.cells()
+    }
+}
+
+impl Snapshot {
+    pub fn cursor(&self) -> isize {
+        self.cursor
+    }
+
+    pub fn relative_base(&self) -> isize {
+        self.relative_base
+    }
+
+    /// Writes the snapshot in a compact binary form: a magic header followed
+    /// by zigzag varints, with memory stored as runs of non-zero cells.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut buf = MAGIC.to_vec();
+        write_varint(&mut buf, self.cursor);
+        write_varint(&mut buf, self.relative_base);
+        write_varint(&mut buf, self.last_diag_code);
+        write_varint(&mut buf, self.input.len() as isize);
+        for value in &self.input {
+            write_varint(&mut buf, *value);
+        }
+        write_varint(&mut buf, self.output.len() as isize);
+        for value in &self.output {
+            write_varint(&mut buf, *value);
+        }
+        let cells = self.memory.cells();
+        let mut runs: Vec<(usize, Vec<isize>)> = Vec::new();
+        for (addr, value) in cells {
+            match runs.last_mut() {
+                Some((start, values)) if *start + values.len() == addr => values.push(value),
+                _ => runs.push((addr, vec![value])),
+            }
+        }
+        write_varint(&mut buf, runs.len() as isize);
+        for (start, values) in runs {
+            write_varint(&mut buf, start as isize);
+            write_varint(&mut buf, values.len() as isize);
+            for value in values {
+                write_varint(&mut buf, value);
+            }
+        }
+        writer.write_all(&buf)
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Snapshot> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not an Intcode snapshot"));
+        }
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut bytes = data.into_iter();
+        let mut next = || read_varint(&mut bytes);
+        let cursor = next()?;
+        let relative_base = next()?;
+        let last_diag_code = next()?;
+        let mut input = Vec::new();
+        for _ in 0..length(next()?)? {
+            input.push(next()?);
+        }
+        let mut output = Vec::new();
+        for _ in 0..length(next()?)? {
+            output.push(next()?);
+        }
+        let mut memory = Memory::default();
+        for _ in 0..length(next()?)? {
+            let start = length(next()?)?;
+            for offset in 0..length(next()?)? {
+                memory.set(start + offset, next()?);
+            }
+        }
+        Ok(Snapshot {
+            memory,
+            cursor,
+            relative_base,
+            input,
+            output,
+            last_diag_code,
+        })
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn length(value: isize) -> io::Result<usize> {
+    if value < 0 {
+        Err(invalid("negative length in snapshot"))
+    } else {
+        Ok(value as usize)
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: isize) {
+    let mut zigzag = ((value << 1) ^ (value >> (isize::BITS - 1))) as usize;
+    loop {
+        let byte = (zigzag & 0x7f) as u8;
+        zigzag >>= 7;
+        if zigzag == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn read_varint<I: Iterator<Item = u8>>(bytes: &mut I) -> io::Result<isize> {
+    let mut zigzag = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = bytes.next().ok_or(io::ErrorKind::UnexpectedEof)?;
+        if shift >= usize::BITS {
+            return Err(invalid("varint overflow in snapshot"));
+        }
+        zigzag |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok((zigzag >> 1) as isize ^ -((zigzag & 1) as isize));
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Program, Status};
+
+    #[test]
+    fn test_round_trip() {
+        let input = crate::parse("3,100,109,-7,1101,3,4,1000000,4,100,3,100,4,100,99");
+        let mut program = Program::new(&input, &[42, -9_000_000_000]);
+        for _ in 0..3 {
+            program.step().unwrap();
+        }
+        let snapshot = program.snapshot();
+        let mut file = Vec::new();
+        snapshot.write_to(&mut file).unwrap();
+        let restored = Snapshot::read_from(&file[..]).unwrap();
+        assert_eq!(snapshot, restored);
+        assert_eq!(-7, restored.relative_base());
+
+        let mut resumed = Program::new(&[], &[]);
+        resumed.restore(&restored);
+        assert_eq!(Ok(Status::Output(42)), resumed.step());
+        assert_eq!(Ok(Status::Running), resumed.step());
+        assert_eq!(Ok(Status::Output(-9_000_000_000)), resumed.step());
+        assert_eq!(7, resumed.read(1_000_000).unwrap());
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let mut program = Program::new(&crate::parse("3,9,4,9,3,9,4,9,99,0"), &[1]);
+        assert_eq!(Ok(Status::NeedsInput), program.run());
+        let checkpoint = program.snapshot();
+        let mut file = Vec::new();
+        checkpoint.write_to(&mut file).unwrap();
+        assert_eq!(checkpoint, Snapshot::read_from(&file[..]).unwrap());
+        program.add_input(2);
+        program.run().unwrap();
+        assert_eq!(2, program.diag_code());
+        program.restore(&checkpoint);
+        program.add_input(3);
+        program.run().unwrap();
+        assert_eq!(3, program.diag_code());
+        // Outputs of the abandoned run are gone, earlier ones are kept.
+        assert_eq!(vec![1, 3], program.take_output());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Snapshot::read_from(&b"nope"[..]).is_err());
+        assert!(Snapshot::read_from(&b"ICS2\x02"[..]).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::{InputSource, Memory, Opcode, OutputSink, ParamMode, Program, Status, VmError};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// Instructions at or above this address are never decoded ahead.
+const MAX_DECODED: usize = 1 << 20;
+
+/// Which interpreter executes instructions. Both produce the same results,
+/// errors included, and work with every tracer, limit and watchpoint.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Engine {
+    /// Decodes each instruction from memory every time it runs.
+    #[default]
+    Reference,
+    /// Runs instructions decoded once, when the engine is selected, into
+    /// handlers with resolved operands. Writes into decoded code mark those
+    /// decodes stale; stale code and code written later runs on the
+    /// reference interpreter, so self-modifying programs behave the same.
+    Threaded,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Immediate(isize),
+    Position(isize),
+    Relative(isize),
+}
+
+type Handler = fn(&mut Program, &Op) -> Result<Status, VmError>;
+
+/// A decoded instruction: the handler for its opcode and its operands.
+#[derive(Debug, Clone, Copy)]
+struct Op {
+    handler: Handler,
+    operands: [Operand; 3],
+    width: usize,
+}
+
+/// Decoded instructions by address. Clones share the decodes and only keep
+/// their own record of which ones their writes made stale, so copying a
+/// loaded program for every run of a parameter sweep stays cheap.
+#[derive(Debug, Clone)]
+pub(crate) struct Cache {
+    ops: Arc<Vec<Option<Op>>>,
+    /// Bitmap of stale addresses, empty until the first stale decode.
+    stale: Vec<u64>,
+}
+
+impl Cache {
+    /// Decodes every cell of `memory` that holds a valid instruction.
+    pub(crate) fn new(memory: &Memory) -> Self {
+        let cells: Vec<_> = memory
+            .cells()
+            .into_iter()
+            .filter(|&(addr, _)| addr < MAX_DECODED)
+            .collect();
+        let len = cells.last().map_or(0, |&(addr, _)| addr + 1);
+        let mut ops = vec![None; len];
+        for (addr, _) in cells {
+            ops[addr] = decode(memory, addr as isize);
+        }
+        Cache {
+            ops: Arc::new(ops),
+            stale: Vec::new(),
+        }
+    }
+
+    fn fetch(&self, cursor: isize) -> Option<&Op> {
+        let addr = usize::try_from(cursor).ok()?;
+        let op = self.ops.get(addr)?.as_ref()?;
+        match self.stale.get(addr / 64) {
+            Some(bits) if bits & (1 << (addr % 64)) != 0 => None,
+            _ => Some(op),
+        }
+    }
+
+    /// Marks the decodes that `cell` is part of as stale.
+    pub(crate) fn invalidate(&mut self, cell: usize) {
+        for addr in cell.saturating_sub(3)..=cell {
+            if let Some(Some(op)) = self.ops.get(addr) {
+                if addr + op.width > cell {
+                    if self.stale.is_empty() {
+                        self.stale = vec![0; self.ops.len() / 64 + 1];
+                    }
+                    self.stale[addr / 64] |= 1 << (addr % 64);
+                }
+            }
+        }
+    }
+}
+
+/// Decodes the instruction at `addr`. Anything that fails to decode is left
+/// to the reference interpreter, which reports the error.
+fn decode(memory: &Memory, addr: isize) -> Option<Op> {
+    let word = memory.get(addr as usize);
+    let opcode = Opcode::decode(word).ok()?;
+    let mut operands = [Operand::Immediate(0); 3];
+    for (idx, operand) in operands.iter_mut().enumerate().take(opcode.arity()) {
+        let value = memory.get(addr as usize + idx + 1);
+        *operand = match ParamMode::decode(word, idx + 1).ok()? {
+            ParamMode::Immediate if opcode.write_param() == Some(idx + 1) => return None,
+            ParamMode::Immediate => Operand::Immediate(value),
+            ParamMode::Position => Operand::Position(value),
+            ParamMode::Relative => Operand::Relative(value),
+        };
+    }
+    let handler: Handler = match opcode {
+        Opcode::Add => add,
+        Opcode::Mul => mul,
+        Opcode::Input => input,
+        Opcode::Output => output,
+        Opcode::JumpTrue => jump_true,
+        Opcode::JumpFalse => jump_false,
+        Opcode::LessThan => less_than,
+        Opcode::Equal => equal,
+        Opcode::RelativeBase => relative_base,
+        Opcode::Halt => halt,
+    };
+    Some(Op {
+        handler,
+        operands,
+        width: 1 + opcode.arity(),
+    })
+}
+
+/// Executes the instruction at the cursor from its cached decode.
+pub(crate) fn execute(program: &mut Program) -> Result<Status, VmError> {
+    let op = match &program.cache {
+        Some(cache) => cache.fetch(program.cursor).copied(),
+        None => None,
+    };
+    match op {
+        Some(op) => (op.handler)(program, &op),
+        None => program.interpret(),
+    }
+}
+
+/// `Program::run_with` without any tracing, limits or watchpoints attached.
+pub(crate) fn run<I, O>(
+    program: &mut Program,
+    input: &mut I,
+    output: &mut O,
+) -> Result<Status, VmError>
+where
+    I: InputSource + ?Sized,
+    O: OutputSink + ?Sized,
+{
+    loop {
+        match execute(program)? {
+            Status::Running => continue,
+            Status::Output(value) => output.output(value),
+            Status::NeedsInput => match input.next_input() {
+                Some(value) => program.add_input(value),
+                None => return Ok(Status::NeedsInput),
+            },
+            status => return Ok(status),
+        }
+    }
+}
+
+fn value(program: &Program, operand: Operand) -> Result<isize, VmError> {
+    match operand {
+        Operand::Immediate(value) => Ok(value),
+        Operand::Position(addr) => program.load(addr),
+        Operand::Relative(offset) => program.load(program.add(program.relative_base, offset)?),
+    }
+}
+
+fn store(program: &mut Program, operand: Operand, value: isize) -> Result<(), VmError> {
+    let position = match operand {
+        Operand::Relative(offset) => program.add(program.relative_base, offset)?,
+        Operand::Position(addr) | Operand::Immediate(addr) => addr,
+    };
+    program.store(position, value)
+}
+
+/// The address of the instruction after `op`. Like the reference
+/// interpreter, this fails before anything else if `op` reaches past the
+/// highest address.
+fn next(program: &Program, op: &Op) -> Result<isize, VmError> {
+    program.add(program.cursor, op.width as isize)
+}
+
+fn add(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    let sum = program.add(
+        value(program, op.operands[0])?,
+        value(program, op.operands[1])?,
+    )?;
+    store(program, op.operands[2], sum)?;
+    program.cursor = next;
+    Ok(Status::Running)
+}
+
+fn mul(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    let product = program.mul(
+        value(program, op.operands[0])?,
+        value(program, op.operands[1])?,
+    )?;
+    store(program, op.operands[2], product)?;
+    program.cursor = next;
+    Ok(Status::Running)
+}
+
+fn input(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    match program.input.front() {
+        Some(&input) => {
+            let next = next(program, op)?;
+            store(program, op.operands[0], input)?;
+            program.input.pop_front();
+            program.cursor = next;
+            Ok(Status::Running)
+        }
+        None => Ok(Status::NeedsInput),
+    }
+}
+
+fn output(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    program.last_diag_code = value(program, op.operands[0])?;
+    program.cursor = next;
+    Ok(Status::Output(program.last_diag_code))
+}
+
+fn jump_true(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    program.cursor = if value(program, op.operands[0])? != 0 {
+        value(program, op.operands[1])?
+    } else {
+        next
+    };
+    Ok(Status::Running)
+}
+
+fn jump_false(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    program.cursor = if value(program, op.operands[0])? == 0 {
+        value(program, op.operands[1])?
+    } else {
+        next
+    };
+    Ok(Status::Running)
+}
+
+fn less_than(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    let less = value(program, op.operands[0])? < value(program, op.operands[1])?;
+    store(program, op.operands[2], less.into())?;
+    program.cursor = next;
+    Ok(Status::Running)
+}
+
+fn equal(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    let equal = value(program, op.operands[0])? == value(program, op.operands[1])?;
+    store(program, op.operands[2], equal.into())?;
+    program.cursor = next;
+    Ok(Status::Running)
+}
+
+fn relative_base(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    let next = next(program, op)?;
+    program.relative_base = program.add(program.relative_base, value(program, op.operands[0])?)?;
+    program.cursor = next;
+    Ok(Status::Running)
+}
+
+fn halt(program: &mut Program, op: &Op) -> Result<Status, VmError> {
+    next(program, op)?;
+    Ok(Status::Halted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn both(program: &[isize], input: &[isize]) -> [(Result<Status, VmError>, Vec<isize>); 2] {
+        [Engine::Reference, Engine::Threaded].map(|engine| {
+            let mut program = Program::new(program, input);
+            program.set_engine(engine);
+            let status = program.run();
+            (status, program.take_output())
+        })
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        let day9 = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let [reference, threaded] = both(&crate::parse(day9), &[]);
+        assert_eq!(reference, threaded);
+        assert_eq!(16, threaded.1.len());
+
+        let [reference, threaded] = both(&crate::parse("3,9,8,9,10,9,4,9,99,-1,8"), &[8]);
+        assert_eq!((Ok(Status::Halted), vec![1]), threaded);
+        assert_eq!(reference, threaded);
+
+        let [reference, threaded] = both(&crate::parse("1101,1,1,-1,99"), &[]);
+        assert!(matches!(threaded.0, Err(VmError::NegativeAddress { .. })));
+        assert_eq!(reference, threaded);
+        let [reference, threaded] = both(&crate::parse("104,1,77"), &[]);
+        assert_eq!(reference, threaded);
+        for words in [
+            "1101,9223372036854775807,1,0,99",
+            "1102,9223372036854775807,2,0,99",
+            "109,9223372036854775807,109,1,99",
+            "109,1,204,9223372036854775807,99",
+        ] {
+            let [reference, threaded] = both(&crate::parse(words), &[]);
+            assert!(matches!(threaded.0, Err(VmError::Overflow { .. })));
+            assert_eq!(reference, threaded);
+        }
+    }
+
+    #[test]
+    fn test_self_modification() {
+        // Counts down from 3, rewriting the operand of the `OUT` at 4 each
+        // time round and finally overwriting the loop's jump with `HLT`.
+        let program = crate::asm::assemble(
+            "loop: ADD [out+1], #-1, [out+1]
+             out:  OUT #3
+                   JT [out+1], #loop
+                   ADD #99, #0, [loop]
+                   JT #1, #loop",
+        )
+        .unwrap();
+        let [reference, threaded] = both(&program, &[]);
+        assert_eq!((Ok(Status::Halted), vec![2, 1, 0]), threaded);
+        assert_eq!(reference, threaded);
+
+        let mut program = Program::new(&crate::parse("104,5,99"), &[]);
+        program.set_engine(Engine::Threaded);
+        program.write(1, 6).unwrap();
+        assert_eq!(Ok(Status::Halted), program.run());
+        assert_eq!(vec![6], program.take_output());
+    }
+
+    #[test]
+    fn test_shared_decodes() {
+        let mut template = Program::new(&crate::parse("1,0,0,0,4,0,99"), &[]);
+        template.set_engine(Engine::Threaded);
+        assert_eq!(Engine::Threaded, template.engine());
+        let mut patched = template.clone();
+        patched.write(1, 4).unwrap();
+        patched.run().unwrap();
+        template.run().unwrap();
+        assert_eq!(
+            (vec![5], vec![2]),
+            (patched.take_output(), template.take_output())
+        );
+
+        // Restoring and stepping back write memory behind the cache's back.
+        let mut program = Program::new(&crate::parse("1,0,0,0,4,0,99"), &[]);
+        program.set_engine(Engine::Threaded);
+        let start = program.snapshot();
+        program.run().unwrap();
+        program.restore(&start);
+        program.set_history(Some(10));
+        program.run().unwrap();
+        while program.step_back() {}
+        program.run().unwrap();
+        assert_eq!(vec![2, 2], program.take_output());
+    }
+}
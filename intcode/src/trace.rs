@@ -0,0 +1,278 @@
+use crate::{Opcode, ParamMode, Program, Status, VmError};
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct TracedParam {
+    pub mode: ParamMode,
+    pub value: isize,
+    /// The value read for input parameters, or the target address for the
+    /// parameter an instruction writes to. `None` if the operand could not
+    /// be resolved, which only happens for one the instruction never reads,
+    /// such as the target of a jump that is not taken.
+    pub resolved: Option<isize>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct MemoryWrite {
+    pub addr: isize,
+    pub old: isize,
+    pub new: isize,
+}
+
+/// One executed instruction and its effects.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TraceRecord {
+    pub step: usize,
+    pub cursor: isize,
+    pub instruction: isize,
+    pub opcode: Opcode,
+    pub params: Vec<TracedParam>,
+    pub write: Option<MemoryWrite>,
+    pub relative_base: Option<(isize, isize)>,
+    pub input: Option<isize>,
+    pub output: Option<isize>,
+}
+
+fn optional(value: Option<isize>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Formats the record as a single line of JSON.
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .map(|param| {
+                format!(
+                    r#"{{"mode":"{}","value":{},"resolved":{}}}"#,
+                    param.mode.name(),
+                    param.value,
+                    optional(param.resolved)
+                )
+            })
+            .collect();
+        let write = self.write.map_or_else(
+            || "null".to_string(),
+            |write| {
+                format!(
+                    r#"{{"addr":{},"old":{},"new":{}}}"#,
+                    write.addr, write.old, write.new
+                )
+            },
+        );
+        let relative_base = self.relative_base.map_or_else(
+            || "null".to_string(),
+            |(from, to)| format!(r#"{{"from":{},"to":{}}}"#, from, to),
+        );
+        write!(
+            f,
+            r#"{{"step":{},"cursor":{},"instruction":{},"opcode":"{}","params":[{}],"write":{},"relative_base":{},"input":{},"output":{}}}"#,
+            self.step,
+            self.cursor,
+            self.instruction,
+            self.opcode.mnemonic(),
+            params.join(","),
+            write,
+            relative_base,
+            optional(self.input),
+            optional(self.output)
+        )
+    }
+}
+
+/// Records executed instructions once attached with `Program::set_tracer`.
+/// Steps are always counted, but only those whose cursor passes the address
+/// filter are recorded, and only until the limit is reached.
+#[derive(Debug, Clone, Default)]
+pub struct Tracer {
+    records: Vec<TraceRecord>,
+    limit: Option<usize>,
+    filter: Option<Range<isize>>,
+    steps: usize,
+    dropped: usize,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_filter(mut self, addrs: Range<isize>) -> Self {
+        self.filter = Some(addrs);
+        self
+    }
+
+    pub fn records(&self) -> &[TraceRecord] {
+        &self.records
+    }
+
+    /// Number of instructions that passed the filter after the limit was hit.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn write_jsonl<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for record in &self.records {
+            writeln!(writer, "{}", record)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn trace(&mut self, program: &mut Program) -> Result<Status, VmError> {
+        let cursor = program.cursor;
+        let wanted = self
+            .filter
+            .as_ref()
+            .map_or(true, |addrs| addrs.contains(&cursor));
+        let full = self.limit.is_some_and(|limit| self.records.len() >= limit);
+        let instruction = match program.instruction() {
+            Ok(instruction) if wanted && !full => instruction,
+            _ => {
+                let result = program.execute();
+                if executed(&result) {
+                    self.steps += 1;
+                    if wanted {
+                        self.dropped += 1;
+                    }
+                }
+                return result;
+            }
+        };
+
+        let mut params = Vec::new();
+        let mut target = None;
+        for (idx, param) in instruction.params.iter().enumerate() {
+            let offset = idx as isize + 1;
+            // An operand that cannot be resolved either faults the
+            // instruction, which is then not recorded, or is never read.
+            let resolved = if instruction.opcode.write_param() == Some(idx + 1) {
+                let addr = program.target(*param).ok();
+                target = addr.map(|addr| (addr, program.peek(addr)));
+                addr
+            } else {
+                program.param(offset).ok()
+            };
+            params.push(TracedParam {
+                mode: param.mode,
+                value: param.value,
+                resolved,
+            });
+        }
+        let relative_base = program.relative_base;
+        let raw = program.peek(cursor);
+
+        let result = program.execute();
+        let status = match result {
+            Ok(status) if executed(&result) => status,
+            _ => return result,
+        };
+        let write = target.map(|(addr, old)| MemoryWrite {
+            addr,
+            old,
+            new: program.peek(addr),
+        });
+        self.records.push(TraceRecord {
+            step: self.steps,
+            cursor,
+            instruction: raw,
+            opcode: instruction.opcode,
+            params,
+            write,
+            relative_base: if program.relative_base != relative_base {
+                Some((relative_base, program.relative_base))
+            } else {
+                None
+            },
+            input: match instruction.opcode {
+                Opcode::Input => write.map(|write| write.new),
+                _ => None,
+            },
+            output: match status {
+                Status::Output(value) => Some(value),
+                _ => None,
+            },
+        });
+        self.steps += 1;
+        result
+    }
+}
+
+fn executed(result: &Result<Status, VmError>) -> bool {
+    match result {
+        Ok(Status::NeedsInput) | Err(_) => false,
+        Ok(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trace() {
+        let mut program = Program::new(&crate::parse("3,12,109,5,22201,7,7,7,204,7,99,0,0"), &[21]);
+        program.set_tracer(Some(Tracer::new()));
+        program.run().unwrap();
+        let tracer = program.take_tracer().unwrap();
+        let lines: Vec<_> = tracer.records().iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            vec![
+                r#"{"step":0,"cursor":0,"instruction":3,"opcode":"IN","params":[{"mode":"position","value":12,"resolved":12}],"write":{"addr":12,"old":0,"new":21},"relative_base":null,"input":21,"output":null}"#,
+                r#"{"step":1,"cursor":2,"instruction":109,"opcode":"ARB","params":[{"mode":"immediate","value":5,"resolved":5}],"write":null,"relative_base":{"from":0,"to":5},"input":null,"output":null}"#,
+                r#"{"step":2,"cursor":4,"instruction":22201,"opcode":"ADD","params":[{"mode":"relative","value":7,"resolved":21},{"mode":"relative","value":7,"resolved":21},{"mode":"relative","value":7,"resolved":12}],"write":{"addr":12,"old":21,"new":42},"relative_base":null,"input":null,"output":null}"#,
+                r#"{"step":3,"cursor":8,"instruction":204,"opcode":"OUT","params":[{"mode":"relative","value":7,"resolved":42}],"write":null,"relative_base":null,"input":null,"output":42}"#,
+                r#"{"step":4,"cursor":10,"instruction":99,"opcode":"HLT","params":[],"write":null,"relative_base":null,"input":null,"output":null}"#,
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_limit_and_filter() {
+        let mut program = Program::new(&crate::parse("3,12,109,5,22201,7,7,7,204,7,99,0,0"), &[21]);
+        program.set_tracer(Some(Tracer::new().with_filter(2..9).with_limit(1)));
+        program.run().unwrap();
+        let tracer = program.take_tracer().unwrap();
+        assert_eq!(1, tracer.records().len());
+        assert_eq!(1, tracer.records()[0].step);
+        assert_eq!(2, tracer.dropped());
+        let mut jsonl = Vec::new();
+        tracer.write_jsonl(&mut jsonl).unwrap();
+        assert_eq!(1, String::from_utf8(jsonl).unwrap().lines().count());
+    }
+
+    #[test]
+    fn test_unread_operand() {
+        let mut program = Program::new(&crate::parse("105,0,-1,99"), &[]);
+        program.set_tracer(Some(Tracer::new()));
+        program.run().unwrap();
+        let tracer = program.take_tracer().unwrap();
+        let lines: Vec<_> = tracer.records().iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            vec![
+                r#"{"step":0,"cursor":0,"instruction":105,"opcode":"JT","params":[{"mode":"immediate","value":0,"resolved":0},{"mode":"position","value":-1,"resolved":null}],"write":null,"relative_base":null,"input":null,"output":null}"#,
+                r#"{"step":1,"cursor":3,"instruction":99,"opcode":"HLT","params":[],"write":null,"relative_base":null,"input":null,"output":null}"#,
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut program =
+            Program::new(&crate::parse("109,1,21101,0,0,9223372036854775807,99"), &[]);
+        program.set_tracer(Some(Tracer::new()));
+        assert!(matches!(
+            program.run(),
+            Err(VmError::Overflow { addr: 2, .. })
+        ));
+    }
+}
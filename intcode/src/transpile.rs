@@ -0,0 +1,494 @@
+use crate::decompile;
+use crate::{
+    InputSource, Instruction, Opcode, OutputSink, Param, ParamMode, Program, Status, VmError,
+};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+const PRELUDE: &str = "\
+//! Transpiled from an Intcode program by `disasm --transpile`. Regenerate it
+//! rather than editing by hand.
+#![allow(clippy::all)]
+
+use intcode::transpile::Machine;
+use intcode::{InputSource, OutputSink, Program, Status, VmError};
+";
+
+const RUN_WITH: &str = "
+/// Continues `program` like `Program::run_with`. Instructions that still hold
+/// the words they were transpiled from run as the Rust below, anything else
+/// on the interpreter.
+pub fn run_with<I, O>(
+    program: &mut Program,
+    input: &mut I,
+    output: &mut O,
+) -> Result<Status, VmError>
+where
+    I: InputSource + ?Sized,
+    O: OutputSink + ?Sized,
+{
+    let mut m = Machine::new(program, &CODE, &NATIVE);
+    loop {
+        let pc = m.cursor();
+        if !m.native(pc) {
+            match m.interpret(input, output)? {
+                Some(status) => return Ok(status),
+                None => continue,
+            }
+        }
+        match pc {
+";
+
+/// Translates `program` into the source of a Rust module. Its `CODE` is the
+/// program to load with `Program::new`, and its `run_with` runs any program
+/// loaded from it like `Program::run_with` does, with the same results and
+/// errors.
+///
+/// Instructions reachable from address 0 without running the program,
+/// following the return addresses calls store, become match arms. Code that
+/// the program writes into, or only reaches through indirect jumps, is left
+/// to the interpreter, as is compiled code whose words differ at runtime
+/// because they were patched before the run or written during it.
+///
+/// This is not a faster way to run a program: every instruction still goes
+/// through the `match` on the cursor and has its words checked, which costs
+/// about as much as the threaded engine's dispatch. The module is for reading
+/// a program as Rust and cross-checking the interpreter against it.
+pub fn transpile(program: &[isize]) -> String {
+    let code = decompile::reachable(program);
+    let written: BTreeSet<_> = code.values().filter_map(position_write).collect();
+    let native: Vec<_> = code
+        .iter()
+        .filter(|(&addr, instruction)| {
+            let cells = addr..addr + instruction.width();
+            compilable(instruction) && !cells.into_iter().any(|cell| written.contains(&cell))
+        })
+        .collect();
+
+    let mut out = PRELUDE.to_string();
+    writeln!(out, "\n/// The program as transpiled.").unwrap();
+    write_array(&mut out, "pub const CODE", "isize", program);
+    writeln!(out, "\n/// Addresses of the instructions compiled below.").unwrap();
+    let addrs: Vec<_> = native.iter().map(|(&addr, _)| addr).collect();
+    write_array(&mut out, "const NATIVE", "usize", &addrs);
+    out += RUN_WITH;
+    for (&addr, instruction) in native {
+        writeln!(out, "            // {}: {}", addr, instruction).unwrap();
+        writeln!(out, "            {} => {{", addr).unwrap();
+        for line in body(addr, instruction) {
+            writeln!(out, "                {}", line).unwrap();
+        }
+        writeln!(out, "            }}").unwrap();
+    }
+    out += "            _ => unreachable!(),\n        }\n    }\n}\n";
+    out
+}
+
+fn write_array<T: ToString>(out: &mut String, item: &str, ty: &str, values: &[T]) {
+    writeln!(out, "{}: [{}; {}] = [", item, ty, values.len()).unwrap();
+    for chunk in values.chunks(16) {
+        let line: Vec<_> = chunk.iter().map(T::to_string).collect();
+        writeln!(out, "    {},", line.join(", ")).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+}
+
+/// The cell an instruction writes, if it is fixed.
+fn position_write(instruction: &Instruction) -> Option<usize> {
+    let param = instruction.params[instruction.opcode.write_param()? - 1];
+    match param.mode {
+        ParamMode::Position => usize::try_from(param.value).ok(),
+        _ => None,
+    }
+}
+
+/// Whether the instruction's constants can be folded without overflowing.
+/// Instructions that always overflow are left to the interpreter, which
+/// reports `VmError::Overflow`.
+fn compilable(instruction: &Instruction) -> bool {
+    match instruction.opcode {
+        Opcode::Add | Opcode::Mul => {
+            let (a, b) = (instruction.params[0], instruction.params[1]);
+            constant(a).is_none()
+                || constant(b).is_none()
+                || fold(instruction.opcode, a, b).is_some()
+        }
+        _ => true,
+    }
+}
+
+fn constant(param: Param) -> Option<isize> {
+    match param.mode {
+        ParamMode::Immediate => Some(param.value),
+        _ => None,
+    }
+}
+
+fn fold(opcode: Opcode, a: Param, b: Param) -> Option<isize> {
+    let (a, b) = (constant(a)?, constant(b)?);
+    match opcode {
+        Opcode::Add => a.checked_add(b),
+        Opcode::Mul => a.checked_mul(b),
+        Opcode::LessThan => Some((a < b).into()),
+        Opcode::Equal => Some((a == b).into()),
+        _ => None,
+    }
+}
+
+fn target(param: Param) -> String {
+    match param.mode {
+        ParamMode::Relative if param.value == 0 => "m.base()".to_string(),
+        ParamMode::Relative => format!("m.relative({})?", param.value),
+        _ => param.value.to_string(),
+    }
+}
+
+fn value(param: Param) -> String {
+    match param.mode {
+        ParamMode::Immediate => param.value.to_string(),
+        _ => format!("m.load({})?", target(param)),
+    }
+}
+
+/// The statements executing `instruction` at `addr`.
+fn body(addr: usize, instruction: &Instruction) -> Vec<String> {
+    let params = &instruction.params;
+    let next = format!("m.jump({});", addr + instruction.width());
+    let operand = |idx: usize| value(params[idx]);
+    let operation = |op: &str| match fold(instruction.opcode, params[0], params[1]) {
+        Some(value) => value.to_string(),
+        None => match op {
+            "+" => format!("m.add({}, {})?", operand(0), operand(1)),
+            "*" => format!("m.mul({}, {})?", operand(0), operand(1)),
+            _ => format!("({} {} {}) as isize", operand(0), op, operand(1)),
+        },
+    };
+    // Operands are read before the target is resolved, as the interpreter
+    // does, so both report the same error first.
+    let store = |value: String| match value.parse::<isize>() {
+        Ok(_) => vec![format!("m.store({}, {})?;", target(params[2]), value)],
+        Err(_) => vec![
+            format!("let value = {};", value),
+            format!("m.store({}, value)?;", target(params[2])),
+        ],
+    };
+    let jump = |taken: bool| match constant(params[0]) {
+        Some(condition) if (condition != 0) == taken => vec![format!("m.jump({});", operand(1))],
+        Some(_) => vec![next.clone()],
+        None => vec![
+            format!("if {} {} 0 {{", operand(0), if taken { "!=" } else { "==" }),
+            format!("    m.jump({});", operand(1)),
+            "} else {".to_string(),
+            format!("    {}", next),
+            "}".to_string(),
+        ],
+    };
+    let then = |mut lines: Vec<String>| {
+        lines.push(next.clone());
+        lines
+    };
+    match instruction.opcode {
+        Opcode::Add => then(store(operation("+"))),
+        Opcode::Mul => then(store(operation("*"))),
+        Opcode::LessThan => then(store(operation("<"))),
+        Opcode::Equal => then(store(operation("=="))),
+        Opcode::Input => vec![
+            "if !m.ready(input) {".to_string(),
+            "    return Ok(Status::NeedsInput);".to_string(),
+            "}".to_string(),
+            format!("m.input({})?;", target(params[0])),
+            next,
+        ],
+        Opcode::Output => vec![format!("m.output(output, {});", operand(0)), next],
+        Opcode::JumpTrue => jump(true),
+        Opcode::JumpFalse => jump(false),
+        Opcode::RelativeBase => vec![format!("m.adjust_base({})?;", operand(0)), next],
+        Opcode::Halt => vec!["return Ok(Status::Halted);".to_string()],
+    }
+}
+
+/// Whether the compiled instruction at an address may run.
+#[derive(Clone, Copy)]
+enum Compiled {
+    /// None starts here, or memory no longer holds its words.
+    Missing,
+    /// One `width` cells long, not yet compared with memory.
+    Unchecked(usize),
+    /// One `width` cells long, matching memory.
+    Checked(usize),
+}
+
+/// What transpiled code runs on: the program it was handed, and which of its
+/// compiled instructions still match memory. Not meant to be used directly.
+pub struct Machine<'a> {
+    program: &'a mut Program,
+    code: &'a [isize],
+    compiled: Vec<Compiled>,
+}
+
+impl<'a> Machine<'a> {
+    /// The instructions at `native` in `code` run compiled where `program`
+    /// holds the same words, which is checked as they are reached, and as
+    /// long as nothing traces, profiles or limits the program.
+    pub fn new(program: &'a mut Program, code: &'a [isize], native: &[usize]) -> Self {
+        let mut compiled = Vec::new();
+        if program.plain() {
+            compiled = vec![Compiled::Missing; native.iter().max().map_or(0, |&addr| addr + 1)];
+            for &addr in native {
+                if let Ok(opcode) = Opcode::decode(code[addr]) {
+                    compiled[addr] = Compiled::Unchecked(1 + opcode.arity());
+                }
+            }
+        }
+        Machine {
+            program,
+            code,
+            compiled,
+        }
+    }
+
+    pub fn cursor(&self) -> isize {
+        self.program.cursor
+    }
+
+    /// Whether the instruction at `addr` runs compiled.
+    pub fn native(&mut self, addr: isize) -> bool {
+        let addr = match usize::try_from(addr) {
+            Ok(addr) => addr,
+            Err(_) => return false,
+        };
+        match self.compiled.get(addr) {
+            Some(Compiled::Checked(_)) => true,
+            Some(&Compiled::Unchecked(width)) => {
+                let (memory, code) = (&self.program.memory, self.code);
+                let matches = (addr..addr + width)
+                    .all(|cell| memory.get(cell) == code.get(cell).copied().unwrap_or(0));
+                self.compiled[addr] = if matches {
+                    Compiled::Checked(width)
+                } else {
+                    Compiled::Missing
+                };
+                matches
+            }
+            _ => false,
+        }
+    }
+
+    pub fn base(&self) -> isize {
+        self.program.relative_base
+    }
+
+    /// The address `offset` cells from the relative base.
+    pub fn relative(&self, offset: isize) -> Result<isize, VmError> {
+        self.program.add(self.program.relative_base, offset)
+    }
+
+    pub fn add(&self, a: isize, b: isize) -> Result<isize, VmError> {
+        self.program.add(a, b)
+    }
+
+    pub fn mul(&self, a: isize, b: isize) -> Result<isize, VmError> {
+        self.program.mul(a, b)
+    }
+
+    pub fn load(&self, target: isize) -> Result<isize, VmError> {
+        self.program.load(target)
+    }
+
+    pub fn store(&mut self, target: isize, value: isize) -> Result<(), VmError> {
+        self.program.store(target, value)?;
+        self.invalidate(target as usize);
+        Ok(())
+    }
+
+    /// Whether an input is queued, reading one from `input` once the
+    /// program's queue is empty.
+    pub fn ready<I>(&mut self, input: &mut I) -> bool
+    where
+        I: InputSource + ?Sized,
+    {
+        if self.program.input.is_empty() {
+            match input.next_input() {
+                Some(value) => self.program.add_input(value),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Stores the queued input at `target` once `ready` said there is one.
+    pub fn input(&mut self, target: isize) -> Result<(), VmError> {
+        self.store(target, self.program.input[0])?;
+        self.program.input.pop_front();
+        Ok(())
+    }
+
+    pub fn output<O>(&mut self, output: &mut O, value: isize)
+    where
+        O: OutputSink + ?Sized,
+    {
+        self.program.last_diag_code = value;
+        output.output(value);
+    }
+
+    pub fn jump(&mut self, target: isize) {
+        self.program.cursor = target;
+    }
+
+    pub fn adjust_base(&mut self, delta: isize) -> Result<(), VmError> {
+        self.program.relative_base = self.relative(delta)?;
+        Ok(())
+    }
+
+    /// Steps the interpreter once, handling input and output like
+    /// `Program::run_with`. Returns the status to stop with, if any.
+    pub fn interpret<I, O>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<Option<Status>, VmError>
+    where
+        I: InputSource + ?Sized,
+        O: OutputSink + ?Sized,
+    {
+        let status = if self.compiled.is_empty() {
+            self.program.step()?
+        } else {
+            let written = self.written();
+            let status = self.program.execute_instruction()?;
+            if let Some(cell) = written {
+                self.invalidate(cell);
+            }
+            status
+        };
+        Ok(match status {
+            Status::Running => None,
+            Status::Output(value) => {
+                output.output(value);
+                None
+            }
+            Status::NeedsInput => match input.next_input() {
+                Some(value) => {
+                    self.program.add_input(value);
+                    None
+                }
+                None => Some(Status::NeedsInput),
+            },
+            status => Some(status),
+        })
+    }
+
+    /// The cell the instruction at the cursor writes, if any.
+    fn written(&self) -> Option<usize> {
+        let program = &self.program;
+        let word = program.memory.get(usize::try_from(program.cursor).ok()?);
+        let param = Opcode::decode(word).ok()?.write_param()?;
+        let value = program.memory.get(program.cursor as usize + param);
+        let target = match ParamMode::decode(word, param) {
+            Ok(ParamMode::Relative) => program.relative_base.checked_add(value)?,
+            _ => value,
+        };
+        usize::try_from(target).ok()
+    }
+
+    /// Has the instructions `cell` is part of compared with memory again
+    /// before they next run.
+    fn invalidate(&mut self, cell: usize) {
+        for addr in cell.saturating_sub(3)..=cell {
+            if let Some(Compiled::Checked(width)) = self.compiled.get(addr).copied() {
+                if addr + width > cell {
+                    self.compiled[addr] = Compiled::Unchecked(width);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_transpile() {
+        // Outputs whether the input is 8, then patches the comparison into
+        // a halt and jumps back to it.
+        let program = crate::asm::assemble(
+            "      IN [x]
+             cmp:  EQ [x], #8, [x]
+                   OUT [x]
+                   ADD #99, #0, [cmp]
+                   JT #1, #cmp
+             x:    .data 0",
+        )
+        .unwrap();
+        let source = transpile(&program);
+        assert!(source.starts_with(PRELUDE));
+        assert!(source.contains("pub const CODE: [isize; 16] = [\n    3, 15, 1008, 15, 8,"));
+        assert!(source.contains("const NATIVE: [usize; 4] = [\n    0, 6, 8, 12,\n];"));
+        assert!(source.ends_with(
+            "            // 0: IN [15]
+            0 => {
+                if !m.ready(input) {
+                    return Ok(Status::NeedsInput);
+                }
+                m.input(15)?;
+                m.jump(2);
+            }
+            // 6: OUT [15]
+            6 => {
+                m.output(output, m.load(15)?);
+                m.jump(8);
+            }
+            // 8: ADD #99, #0, [2]
+            8 => {
+                m.store(2, 99)?;
+                m.jump(12);
+            }
+            // 12: JT #1, #2
+            12 => {
+                m.jump(2);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+"
+        ));
+    }
+
+    #[test]
+    fn test_operands() {
+        let add = Instruction::decode(&[22201, -1, 3, 0], 4).unwrap();
+        assert_eq!(
+            vec![
+                "let value = m.add(m.load(m.relative(-1)?)?, m.load(m.relative(3)?)?)?;",
+                "m.store(m.base(), value)?;",
+                "m.jump(8);"
+            ],
+            body(4, &add)
+        );
+        let less = Instruction::decode(&[1007, 7, -2, 9], 0).unwrap();
+        assert_eq!(
+            vec![
+                "let value = (m.load(7)? < -2) as isize;",
+                "m.store(9, value)?;"
+            ],
+            body(0, &less)[..2]
+        );
+        let decrement = Instruction::decode(&[1001, 7, -1, 7], 0).unwrap();
+        assert_eq!(
+            "let value = m.add(m.load(7)?, -1)?;",
+            body(0, &decrement)[0]
+        );
+        let base = Instruction::decode(&[209, 2], 0).unwrap();
+        assert_eq!(
+            "m.adjust_base(m.load(m.relative(2)?)?)?;",
+            body(0, &base)[0]
+        );
+        let jump = Instruction::decode(&[1106, 0, 20], 0).unwrap();
+        assert_eq!(vec!["m.jump(20);"], body(0, &jump));
+        let overflow = Instruction::decode(&[1102, isize::MAX, 2, 0], 0).unwrap();
+        assert!(!compilable(&overflow));
+    }
+}
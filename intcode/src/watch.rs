@@ -0,0 +1,215 @@
+use crate::{ParamMode, Program, Status, VmError};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn matches(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+/// A watched access. Reads fire before the instruction executes, so `old`
+/// and `new` are equal; writes fire after it with the overwritten value.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct WatchEvent {
+    pub access: Access,
+    pub addr: isize,
+    pub cursor: isize,
+    pub old: isize,
+    pub new: isize,
+}
+
+pub type WatchCallback = Arc<dyn Fn(&WatchEvent) + Send + Sync>;
+
+/// Watches a range of addresses. By default a hit stops execution with
+/// `Status::Watchpoint`, with a callback it is reported and execution goes on.
+#[derive(Clone)]
+pub struct Watchpoint {
+    addrs: Range<isize>,
+    access: Access,
+    callback: Option<WatchCallback>,
+}
+
+impl Watchpoint {
+    pub fn new(addrs: Range<isize>, access: Access) -> Self {
+        Self {
+            addrs,
+            access,
+            callback: None,
+        }
+    }
+
+    pub fn with_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&WatchEvent) + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    pub fn addrs(&self) -> &Range<isize> {
+        &self.addrs
+    }
+
+    pub fn access(&self) -> Access {
+        self.access
+    }
+}
+
+impl fmt::Debug for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watchpoint")
+            .field("addrs", &self.addrs)
+            .field("access", &self.access)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// Delivers `event` to every matching watchpoint and returns it if one of
+/// them asks for execution to stop.
+pub(crate) fn fire(watchpoints: &[Watchpoint], event: WatchEvent) -> Option<WatchEvent> {
+    let mut stop = None;
+    for watchpoint in watchpoints {
+        if !watchpoint.access.matches(event.access) || !watchpoint.addrs.contains(&event.addr) {
+            continue;
+        }
+        match &watchpoint.callback {
+            Some(callback) => callback(&event),
+            None => stop = stop.or(Some(event)),
+        }
+    }
+    stop
+}
+
+/// Steps `program` while checking the data accesses of the instruction at
+/// the cursor against its watchpoints.
+pub(crate) fn step(program: &mut Program) -> Result<Status, VmError> {
+    let instruction = match program.instruction() {
+        Ok(instruction) => instruction,
+        Err(_) => return program.traced_step(),
+    };
+    let cursor = program.cursor;
+    let mut reads = Vec::new();
+    let mut write = None;
+    for (idx, param) in instruction.params.iter().enumerate() {
+        if param.mode == ParamMode::Immediate {
+            continue;
+        }
+        let addr = match program.target(*param) {
+            Ok(addr) => addr,
+            Err(_) => return program.traced_step(),
+        };
+        if instruction.opcode.write_param() == Some(idx + 1) {
+            write = Some(addr);
+        } else {
+            reads.push(addr);
+        }
+    }
+
+    if !program.watch_resume {
+        let mut stop = None;
+        for addr in reads {
+            let value = program.peek(addr);
+            let event = WatchEvent {
+                access: Access::Read,
+                addr,
+                cursor,
+                old: value,
+                new: value,
+            };
+            stop = stop.or(fire(&program.watchpoints, event));
+        }
+        if let Some(event) = stop {
+            program.watch_resume = true;
+            return Ok(Status::Watchpoint(event));
+        }
+    }
+    program.watch_resume = false;
+
+    let old = write.map(|addr| program.peek(addr));
+    let status = program.traced_step()?;
+    if let (Some(addr), Some(old), Status::Running) = (write, old, status) {
+        let event = WatchEvent {
+            access: Access::Write,
+            addr,
+            cursor,
+            old,
+            new: program.peek(addr),
+        };
+        if let Some(event) = fire(&program.watchpoints, event) {
+            return Ok(Status::Watchpoint(event));
+        }
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_break_on_write() {
+        let program = crate::asm::assemble(
+            "loop: ADD [score], #5, [score]\nLT [score], #12, [flag]\nJT [flag], #loop\nHLT\nscore: .data 0\nflag: .data 0",
+        )
+        .unwrap();
+        let mut program = Program::new(&program, &[]);
+        program.add_watchpoint(Watchpoint::new(12..13, Access::Write));
+        let mut hits = Vec::new();
+        while let Status::Watchpoint(event) = program.run().unwrap() {
+            hits.push((event.cursor, event.old, event.new));
+        }
+        assert_eq!(vec![(0, 0, 5), (0, 5, 10), (0, 10, 15)], hits);
+    }
+
+    #[test]
+    fn test_read_callback() {
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let log = reads.clone();
+        let mut program = Program::new(&crate::parse("4,5,4,5,99,42"), &[]);
+        program.add_watchpoint(
+            Watchpoint::new(5..6, Access::Read)
+                .with_callback(move |event| log.lock().unwrap().push(event.cursor)),
+        );
+        assert_eq!(Ok(Status::Halted), program.run());
+        assert_eq!(vec![0, 2], *reads.lock().unwrap());
+    }
+
+    #[test]
+    fn test_break_on_read_resumes() {
+        let mut program = Program::new(&crate::parse("4,5,99,0,0,42"), &[]);
+        program.add_watchpoint(Watchpoint::new(0..10, Access::ReadWrite));
+        let event = WatchEvent {
+            access: Access::Read,
+            addr: 5,
+            cursor: 0,
+            old: 42,
+            new: 42,
+        };
+        assert_eq!(Ok(Status::Watchpoint(event)), program.step());
+        assert_eq!(Ok(Status::Output(42)), program.step());
+        assert!(program.remove_watchpoint(&(0..10)));
+        assert_eq!(Ok(Status::Halted), program.step());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut program =
+            Program::new(&crate::parse("109,1,21101,0,0,9223372036854775807,99"), &[]);
+        program.add_watchpoint(Watchpoint::new(0..10, Access::ReadWrite));
+        assert!(matches!(
+            program.run(),
+            Err(VmError::Overflow { addr: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+//! Runs a transpiled program next to the interpreter. The module in
+//! `transpiled/` is generated with `disasm --transpile`.
+
+use intcode::transpile::transpile;
+use intcode::{Program, Status, VmError};
+use std::collections::VecDeque;
+
+#[rustfmt::skip]
+#[path = "transpiled/countdown.rs"]
+mod countdown;
+
+/// Counts down from its input by writing the operand of its `OUT` through
+/// the relative base, then overwrites the `OUT` with a halt and jumps to it.
+const COUNTDOWN: &str = "
+       ARB #out
+       IN rb+1
+loop:  ADD rb+1, #-1, rb+1
+out:   OUT #0
+       JT rb+1, #loop
+       ADD #99, #0, rb+0
+       JT #1, #out";
+
+type RunWith = fn(&mut Program, &mut VecDeque<isize>, &mut Vec<isize>) -> Result<Status, VmError>;
+
+type Outcome = (
+    Result<Status, VmError>,
+    Vec<isize>,
+    Vec<(usize, isize)>,
+    isize,
+    isize,
+);
+
+fn outcome(program: &Program, status: Result<Status, VmError>, output: Vec<isize>) -> Outcome {
+    let memory = program.memory().cells();
+    (
+        status,
+        output,
+        memory,
+        program.cursor(),
+        program.relative_base(),
+    )
+}
+
+/// Runs `program` with `input` both on the interpreter and through
+/// `run_with`, checks they end in the same state and returns the outputs.
+fn cross_check(program: &mut Program, run_with: RunWith, input: &[isize]) -> Vec<isize> {
+    let mut interpreted = program.clone();
+    let mut output = Vec::new();
+    let status = interpreted.run_with(
+        &mut input.iter().copied().collect::<VecDeque<_>>(),
+        &mut output,
+    );
+    let expected = outcome(&interpreted, status, output);
+
+    let mut output = Vec::new();
+    let status = run_with(program, &mut input.iter().copied().collect(), &mut output);
+    let actual = outcome(program, status, output);
+    assert_eq!(expected, actual);
+    actual.1
+}
+
+#[test]
+fn test_up_to_date() {
+    let program = intcode::asm::assemble(COUNTDOWN).unwrap();
+    assert_eq!(include_str!("transpiled/countdown.rs"), transpile(&program));
+}
+
+#[test]
+fn test_self_modification() {
+    let mut program = Program::new(&countdown::CODE, &[]);
+    assert_eq!(
+        vec![2, 1, 0],
+        cross_check(&mut program, countdown::run_with, &[3])
+    );
+    assert_eq!(99, program.read(8).unwrap());
+}
+
+#[test]
+fn test_suspend_and_resume() {
+    let mut program = Program::new(&countdown::CODE, &[]);
+    assert!(cross_check(&mut program, countdown::run_with, &[]).is_empty());
+    assert_eq!(
+        vec![1, 0],
+        cross_check(&mut program, countdown::run_with, &[2])
+    );
+}
+
+#[test]
+fn test_patched_operands() {
+    // Counting down in threes, the `ADD` runs on the interpreter.
+    let mut program = Program::new(&countdown::CODE, &[]);
+    program.write(6, -3).unwrap();
+    assert_eq!(
+        vec![3, 0],
+        cross_check(&mut program, countdown::run_with, &[6])
+    );
+    // The second decrement overflows.
+    let mut program = Program::new(&countdown::CODE, &[]);
+    program.write(6, isize::MIN).unwrap();
+    cross_check(&mut program, countdown::run_with, &[3]);
+    assert!(matches!(
+        program.run_with(&mut VecDeque::new(), &mut Vec::new()),
+        Err(VmError::Overflow { .. })
+    ));
+}
+
+#[test]
+fn test_limits() {
+    // Limits need every instruction to be counted, so nothing runs compiled.
+    let mut program = Program::new(&countdown::CODE, &[3]);
+    program.set_instruction_limit(Some(5));
+    assert_eq!(vec![2], cross_check(&mut program, countdown::run_with, &[]));
+    assert!(matches!(
+        program.step(),
+        Err(VmError::InstructionLimit { .. })
+    ));
+}
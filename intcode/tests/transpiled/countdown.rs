@@ -0,0 +1,85 @@
+//! Transpiled from an Intcode program by `disasm --transpile`. Regenerate it
+//! rather than editing by hand.
+#![allow(clippy::all)]
+
+use intcode::transpile::Machine;
+use intcode::{InputSource, OutputSink, Program, Status, VmError};
+
+/// The program as transpiled.
+pub const CODE: [isize; 20] = [
+    109, 8, 203, 1, 21201, 1, -1, 1, 104, 0, 1205, 1, 4, 21101, 99, 0,
+    0, 1105, 1, 8,
+];
+
+/// Addresses of the instructions compiled below.
+const NATIVE: [usize; 7] = [
+    0, 2, 4, 8, 10, 13, 17,
+];
+
+/// Continues `program` like `Program::run_with`. Instructions that still hold
+/// the words they were transpiled from run as the Rust below, anything else
+/// on the interpreter.
+pub fn run_with<I, O>(
+    program: &mut Program,
+    input: &mut I,
+    output: &mut O,
+) -> Result<Status, VmError>
+where
+    I: InputSource + ?Sized,
+    O: OutputSink + ?Sized,
+{
+    let mut m = Machine::new(program, &CODE, &NATIVE);
+    loop {
+        let pc = m.cursor();
+        if !m.native(pc) {
+            match m.interpret(input, output)? {
+                Some(status) => return Ok(status),
+                None => continue,
+            }
+        }
+        match pc {
+            // 0: ARB #8
+            0 => {
+                m.adjust_base(8)?;
+                m.jump(2);
+            }
+            // 2: IN rb+1
+            2 => {
+                if !m.ready(input) {
+                    return Ok(Status::NeedsInput);
+                }
+                m.input(m.relative(1)?)?;
+                m.jump(4);
+            }
+            // 4: ADD rb+1, #-1, rb+1
+            4 => {
+                let value = m.add(m.load(m.relative(1)?)?, -1)?;
+                m.store(m.relative(1)?, value)?;
+                m.jump(8);
+            }
+            // 8: OUT #0
+            8 => {
+                m.output(output, 0);
+                m.jump(10);
+            }
+            // 10: JT rb+1, #4
+            10 => {
+                if m.load(m.relative(1)?)? != 0 {
+                    m.jump(4);
+                } else {
+                    m.jump(13);
+                }
+            }
+            // 13: ADD #99, #0, rb+0
+            13 => {
+                m.store(m.base(), 99)?;
+                m.jump(17);
+            }
+            // 17: JT #1, #8
+            17 => {
+                m.jump(8);
+            }
+            _ => unreachable!(),
+        }
+    }
+}